@@ -1,6 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 #[allow(unused)]
-use rpn::{
+use rpn_lib::{
     get_rpn_yard,
     get_rpn_tree,
     solve_numerical,
@@ -1,12 +1,38 @@
-//! Converts and solves infix to postfix (reverse-polish notation [RPM]). Values containing variables cannot be solved, only converted.
-//! To input negatives, type either ' ¬ ' or ' ! '. 
+//! Converts and solves infix to postfix (reverse-polish notation [RPM]). Values containing variables are solved by giving each variable a value, otherwise they can only be converted.
+//! To input negatives, use a leading ' - ', which also negates whole brackets as in -(x+1) and is written ' ~ ' in rpn.
+//! Typing ' ¬ ' or ' ! ' instead makes the value itself negative. 
 //! 
 //! Uses 2 different algorithms to convert, shunting yard or an AST tree post-order traversal. The default is shunting yard due to increased performance.
 //! 'solve_numerical' takes in an numerical infix expression as a string reference and solves it using shunting yard.
+//! 'solve_with_variables' solves an infix expression, taking the value of each variable from a map.
+//! 'solve_with_context' does the same with a 'Context', which also holds constants. pi, e, tau and phi are built in.
+//! Functions are defined in a context with 'Context::define', such as "f(x, y) = x^2 + y", and called like built-in ones.
+//! 'solve_rpn' & 'solve_rpn_with_context' solve rpn that is already converted, such as "3 4 + 2 *", with values separated by spaces.
 //! 'in_to_rpn' converts an infix expression to rpn using shunting.
 //! 'get_rpn_yard' & 'get_rpn_tree' use their respective algorithm to convert.
+//! 'get_rpn_with' & 'solve_with_options' read the input with 'ParseOptions', such as implicit multiplication of 2x or (a+b)(a-b).
+//! 'get_rpn_folded' also calculates the parts made only of numbers, so (2+3)*x is 5 x *.
+//! 'get_prefix' converts to prefix (polish notation) with a pre-order traversal of the AST tree, and 'solve_prefix' solves prefix input.
+//! 'parse' returns the expression as an 'Expr' tree, which can be matched on or folded with the 'Fold' trait.
+//! 'differentiate' takes the derivative of an 'Expr' with respect to a variable, printed as infix with to_string or as rpn with to_stack.
+//! 'simplify' folds constants, removes identities such as x*1, collects like terms and merges powers. 'Simplifier' takes extra rules.
+//! 'get_rpn_simplified' & 'solve_simplified' simplify before converting or solving.
+//! 'rpn_to_in' converts rpn back to infix, only adding the brackets it needs.
+//! The '_with_context' solvers of rpn and prefix, and 'rpn_to_in_with_context', read the names of defined functions as calls.
 //! 
-//! Acceptable operators are +, -, /, ^, *
+//! Numbers can be written as 1.5, .5, 1.5e-3, 6.02E23, 1_000_000, or as hex, binary and octal integers such as 0x1F, 0b1010 and 0o17.
+//! A space ends a number or name, so '1 2' is two values, which need an operator between them unless multiplied implicitly.
+//! Acceptable operators are +, -, /, ^ or **, *, % (modulo) and // (integer division).
+//! How % and // treat negatives is set with 'Context::set_modulo_mode', truncating by default.
+//! Other operators, including ones of several chars such as <<, are added to an 'OperatorTable' with their precedence, associativity and a closure calculating them,
+//! then given to 'ParseOptions::set_operators' for reading and 'Context::set_operators' for solving.
+//! Comparisons <, >, <=, >=, == and != bind more loosely than + and -, then not, and, or, giving true or false.
+//! 'solve_condition' solves such a condition, as in "pressure < 100 and temp >= 0", and 'solve_value' solves either kind of expression.
+//! Conditionals are written 'c ? a : b' or 'if(c, a, b)'. In rpn they become jumps, 'c ?n a :m b', where '?n' skips the next n tokens unless c is true
+//! and ':m' skips the m tokens of the second branch, so only the branch taken is evaluated.
+//! Built-in functions are called as name(a, b), such as sqrt(x) or max(a, b), and are written after their arguments in rpn.
+//! 
+//! Every failure is an 'RpnError', which carries the span of the input that caused it.
 //! 
 //! Example
 //! '''Rust
@@ -24,69 +50,152 @@
 pub mod rpn_convert;
     use rpn_convert::{
     Validate,
-    MathValue, Stack,
+    Stack,
     shunting_yard,
     ast_tree,
+    evaluate,
+    postfix,
+    infix,
+    prefix,
 };
 
-use std::error::Error;
+use std::collections::HashMap;
+
+pub use rpn_convert::{RpnError, Span, Context, ModuloMode, Expr, Fold, ParseOptions, OperatorTable, Operator, Associativity, Value, ValueType};
+pub use rpn_convert::derivative::differentiate;
+pub use rpn_convert::simplify::{simplify, Simplifier};
 
 /// Solves the given numerical expression
-pub fn solve_numerical(input: &str) -> Result<f64, Box<dyn std::error::Error>>{
-    let mut total_stack:Vec<f64> = Vec::new();
+pub fn solve_numerical(input: &str) -> Result<f64, RpnError>{
     let rpn_form: Stack = shunting_yard::convert_in_to_post_fix(input)?;
-    let form_iter = rpn_form.iter();
-
-    for value in form_iter {
-        match value {
-            MathValue::Num(num) => total_stack.push(*num),
-            MathValue::Op(op) => {
-                let b: f64 = total_stack.pop().unwrap();
-                let a: f64 = total_stack.pop().unwrap();
-                total_stack.push(calculate(a, b, *op));
-            },
-            MathValue::Alge(_al) => continue,
-        }
-    }
-    println!("RPN form is: {}", shunting_yard::convert_in_to_post_fix(input)?);
-    Ok(total_stack.pop().unwrap())
+    evaluate::evaluate(&rpn_form, &Context::new())
 }
 
-/// Converts an infix expression to a post fix expression (RPN)
-pub fn in_to_rpn(input: &str) -> Result<String, Box<dyn Error>> {
-    get_rpn_yard(input)
+/// Solves the given expression, substituting each variable with its value from the map
+pub fn solve_with_variables(input: &str, variables: &HashMap<String, f64>) -> Result<f64, RpnError> {
+    let rpn_form: Stack = shunting_yard::convert_in_to_post_fix(input)?;
+    evaluate::evaluate(&rpn_form, &Context::with_variables(variables))
+}
+
+/// Solves the given expression, taking variables and constants from the context
+pub fn solve_with_context(input: &str, context: &Context) -> Result<f64, RpnError> {
+    let rpn_form: Stack = shunting_yard::convert_in_to_post_fix(input)?;
+    evaluate::evaluate(&rpn_form, context)
+}
+
+/// Solves the given expression, which may be a comparison giving true or false,
+/// taking variables and constants from the context
+pub fn solve_value(input: &str, context: &Context) -> Result<Value, RpnError> {
+    let rpn_form: Stack = shunting_yard::convert_in_to_post_fix(input)?;
+    evaluate::evaluate_value(&rpn_form, context)
 }
 
-/// Calculates the solution from the given operators
-/// Works left to right - a op b
-fn calculate(a: f64, b: f64, op: char) -> f64 {
-    match op {
-        '+' => a + b,
-        '-' => a - b,
-        '*' => a * b,
-        '/' => a / b,
-        '^' => a.powf(b),
-        '%' => a % b,
-        _ => panic!("Invalid operations"),
-    }
+/// Solves the given condition, such as "pressure < 100 and temp >= 0",
+/// taking variables and constants from the context
+pub fn solve_condition(input: &str, context: &Context) -> Result<bool, RpnError> {
+    let rpn_form: Stack = shunting_yard::convert_in_to_post_fix(input)?;
+    let span = rpn_form.tokens().iter().map(|token| token.span).reduce(Span::join).unwrap_or_default();
+    evaluate::evaluate_value(&rpn_form, context)?.as_bool()
+        .ok_or(RpnError::TypeMismatch { expected: ValueType::Bool, span })
+}
+
+/// Solves the given rpn expression, with each value and operator separated by whitespace
+pub fn solve_rpn(input: &str) -> Result<f64, RpnError> {
+    solve_rpn_with_context(input, &Context::new())
+}
+
+/// Solves the given rpn expression, taking variables and constants from the context
+pub fn solve_rpn_with_context(input: &str, context: &Context) -> Result<f64, RpnError> {
+    let rpn_form: Stack = postfix::parse_with_context(input, context)?;
+    evaluate::evaluate(&rpn_form, context)
+}
+
+/// Converts an infix expression to a post fix expression (RPN)
+pub fn in_to_rpn(input: &str) -> Result<String, RpnError> {
+    get_rpn_yard(input)
 }
 
 /// Converts an infix expression to a post fix expression (RPN) using shunting yard
-pub fn get_rpn_yard(input: &str) -> Result<String, Box<dyn Error>> {
-    let (is_valid, msg) = Validate::validate_input(input);
-    if is_valid {
-        Ok(shunting_yard::convert_in_to_post_fix(input)?.as_string())
-    } else {
-        Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, msg)))
-    }
+pub fn get_rpn_yard(input: &str) -> Result<String, RpnError> {
+    Validate::validate_input(input)?;
+    Ok(shunting_yard::convert_in_to_post_fix(input)?.as_string())
 }
 
 /// Converts an infix expression to a post fix expression (RPN) using post-order of an AST Tree
-pub fn get_rpn_tree(input: &str) -> Result<String, Box<dyn Error>> {
-    let (is_valid, msg) = Validate::validate_input(input);
-    if is_valid {
-        Ok(ast_tree::convert_in_to_post_fix(input)?.as_string())
-    } else {
-        Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, msg)))
-    }
-}
\ No newline at end of file
+pub fn get_rpn_tree(input: &str) -> Result<String, RpnError> {
+    Validate::validate_input(input)?;
+    Ok(ast_tree::convert_in_to_post_fix(input)?.as_string())
+}
+
+/// Parses an infix expression into an expression tree
+pub fn parse(input: &str) -> Result<Expr, RpnError> {
+    Validate::validate_input(input)?;
+    ast_tree::parse(input)
+}
+
+/// Converts an infix expression to a post fix expression (RPN) using shunting yard,
+/// reading it with the options
+pub fn get_rpn_with(input: &str, options: &ParseOptions) -> Result<String, RpnError> {
+    Validate::validate_input_with(input, options)?;
+    Ok(shunting_yard::convert_in_to_post_fix_with(input, options)?.as_string())
+}
+
+/// Solves the given expression read with the options, taking variables and constants from the context
+pub fn solve_with_options(input: &str, options: &ParseOptions, context: &Context) -> Result<f64, RpnError> {
+    Validate::validate_input_with(input, options)?;
+    let rpn_form: Stack = shunting_yard::convert_in_to_post_fix_with(input, options)?;
+    evaluate::evaluate(&rpn_form, context)
+}
+
+/// Converts an infix expression to a post fix expression (RPN) through its AST Tree,
+/// calculating every part made only of numbers
+pub fn get_rpn_folded(input: &str) -> Result<String, RpnError> {
+    let mut folder = Simplifier::empty();
+    folder.add_rule(rpn_convert::simplify::fold_constants);
+    Ok(folder.simplify(&parse(input)?).to_stack().as_string())
+}
+
+/// Converts an infix expression to a post fix expression (RPN) after simplifying its AST Tree
+pub fn get_rpn_simplified(input: &str) -> Result<String, RpnError> {
+    Ok(simplify(&parse(input)?).to_stack().as_string())
+}
+
+/// Solves the given expression after simplifying it, taking variables and constants from the context.
+/// Every variable needs a value, even one simplified away as in `x - x`.
+/// Parts that can't be calculated may still be simplified away, so `0 * (1 / 0)` is 0
+/// where the other solvers give NaN.
+pub fn solve_simplified(input: &str, context: &Context) -> Result<f64, RpnError> {
+    let expr = parse(input)?;
+    evaluate::check_bound(&ast_tree::convert_in_to_post_fix(input)?, context, &[])?;
+    simplify(&expr).evaluate(context)
+}
+
+/// Converts an infix expression to a prefix expression (polish notation) using pre-order of an AST Tree
+pub fn get_prefix(input: &str) -> Result<String, RpnError> {
+    Validate::validate_input(input)?;
+    Ok(ast_tree::convert_in_to_pre_fix(input)?.as_string())
+}
+
+/// Solves the given prefix expression, with each value and operator separated by whitespace
+pub fn solve_prefix(input: &str) -> Result<f64, RpnError> {
+    solve_prefix_with_context(input, &Context::new())
+}
+
+/// Solves the given prefix expression, taking variables and constants from the context
+pub fn solve_prefix_with_context(input: &str, context: &Context) -> Result<f64, RpnError> {
+    let operators = context.operators();
+    let rpn_form: Stack = prefix::convert_pre_to_post_fix_with(&prefix::parse_with_context(input, context)?, operators)?;
+    evaluate::evaluate(&rpn_form, context)
+}
+
+/// Converts a post fix expression (RPN) back to infix with as few brackets as possible
+pub fn rpn_to_in(input: &str) -> Result<String, RpnError> {
+    infix::convert_post_to_in_fix(&postfix::parse(input)?)
+}
+
+/// Converts a post fix expression (RPN) back to infix, reading it with the operators
+/// and defined functions of the context
+pub fn rpn_to_in_with_context(input: &str, context: &Context) -> Result<String, RpnError> {
+    let operators = context.operators();
+    infix::convert_post_to_in_fix_with(&postfix::parse_with_context(input, context)?, operators)
+}
@@ -1,12 +1,15 @@
 #[allow(unused)]
-use rpn_lib::{solve_numerical, get_rpn_yard, get_rpn_tree};
+use rpn_lib::{solve_numerical, solve_value, solve_rpn, solve_rpn_with_context, get_rpn_yard, get_rpn_tree, Context};
+use rpn_lib::rpn_convert::{diagnostic, Validate};
 use std::io;
 
 fn main() {    
     let mut decision: String;
     let mut input: String;
+    // Functions defined so far, available when solving
+    let mut context = Context::new();
     loop {
-        println!("Enter your equation.\ns for solve (only numerical, not variables),\nr for RPN form (postfix),\nb | q to quit...");
+        println!("Enter your equation.\ns for solve (numbers, comparisons and defined functions),\nr for RPN form (postfix),\np to solve RPN input,\nd to define a function such as f(x, y) = x^2 + y,\nb | q to quit...");
         decision = "".to_string();
         input= "".to_string();
         io::stdin().read_line(&mut decision).expect("Failed to read line");
@@ -18,19 +21,48 @@ fn main() {
             },
             "s" => {
                 io::stdin().read_line(&mut input).expect("Failed to read line");
-                match solve_numerical(&input.trim()) {
+                if !is_valid_or_report(input.trim(), &context) {
+                    continue;
+                }
+                match solve_value(input.trim(), &context) {
                     Ok(answer) => println!("{} = {}\n", input, answer),
-                    Err(e) => println!("Error: {}\n", e),
+                    Err(e) => println!("{}\n", diagnostic::render_with_context(input.trim(), &e, &context)),
                 };
             },
             "r" => {
                 io::stdin().read_line(&mut input).expect("Failed to read line");
-                match get_rpn_yard(&input.trim()) {
+                if !is_valid_or_report(input.trim(), &context) {
+                    continue;
+                }
+                match get_rpn_yard(input.trim()) {
                     Ok(answer) => println!("RPN form: {}\n", answer),
-                    Err(e) => println!("Error: {}\n", e),
+                    Err(e) => println!("{}\n", diagnostic::render_with_context(input.trim(), &e, &context)),
+                };
+            },
+            "d" => {
+                io::stdin().read_line(&mut input).expect("Failed to read line");
+                match context.define(input.trim()) {
+                    Ok(_) => println!("Defined {}\n", input.trim()),
+                    Err(e) => println!("{}\n", diagnostic::render_with_context(input.trim(), &e, &context)),
+                };
+            },
+            "p" => {
+                io::stdin().read_line(&mut input).expect("Failed to read line");
+                match solve_rpn_with_context(input.trim(), &context) {
+                    Ok(answer) => println!("{} = {}\n", input.trim(), answer),
+                    Err(e) => println!("{}\n", diagnostic::render_with_context(input.trim(), &e, &context)),
                 };
             },
             _ => (),
         }        
     }
-}
\ No newline at end of file
+}
+
+/// True if the input is valid, otherwise prints every problem with it at once
+fn is_valid_or_report(input: &str, context: &Context) -> bool {
+    let errors = Validate::validate_all(input);
+    if !errors.is_empty() {
+        println!("{}\n", diagnostic::render_all_with_context(input, &errors, context));
+    }
+    errors.is_empty()
+}
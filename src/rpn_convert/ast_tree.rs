@@ -2,144 +2,361 @@ use super::*;
 
 #[derive(Clone, Debug)]
 struct Node {
-    data: MathValue,
-    left: Option<Box<Node>>,
-    right: Option<Box<Node>>,
+    data: Token,
+    // Operands in the order they were written
+    children: Vec<Node>,
+    // Spans of the `?` and `:` of a conditional, or the commas between the arguments of a call
+    separators: Vec<Span>,
 }
 
 impl Node {
-    fn new(data: MathValue, left: Option<Node>, right: Option<Node>) -> Self {
-        Node {
-            data,
-            left: left.map(Box::new),
-            right: right.map(Box::new),
+    fn new(data: Token, children: Vec<Node>) -> Self {
+        Node { data, children, separators: Vec::new() }
+    }
+}
+
+/// Drops the children one at a time, so a long chain can't overflow the stack
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut node) = pending.pop() {
+            pending.append(&mut node.children);
         }
     }
 }
 
 #[derive(Clone)]
-struct Parser {
-    tokens: Vec<MathValue>,
+struct Parser<'a> {
+    tokens: Vec<Token>,
     current_token_index: usize,
+    operators: &'a OperatorTable,
+    // How many brackets, calls, conditionals and operands the parser is inside
+    depth: usize,
 }
 
-impl Parser {
-    fn try_from(input: &str) -> Result<Parser, Box<dyn Error>> {
-        // Clean Input
-        let input = input.replace(' ', "");
-        let tokens_stack = Stack::try_from(input.as_str())?;
-        let tokens = Vec::from(tokens_stack.elements);
-        Ok(Parser {tokens, current_token_index: 0})
+impl<'a> Parser<'a> {
+    fn try_from(input: &str, options: &'a ParseOptions) -> Result<Parser<'a>, RpnError> {
+        let tokens_stack = Stack::try_from_with(input, options)?;
+        let tokens = tokens_stack.elements;
+        Ok(Parser {tokens, current_token_index: 0, operators: options.operators(), depth: 0})
     }
 
     fn advance(&mut self) {
-        if self.current_token_index < self.tokens.len() - 1 {
+        if self.current_token_index < self.tokens.len() {
             self.current_token_index += 1;
         }
     }
 
-    fn current_token(&self) -> &MathValue {
-        &self.tokens[self.current_token_index]
+    /// The current token, or None once every token is used
+    fn current_token(&self) -> Option<&MathValue> {
+        self.tokens.get(self.current_token_index).map(|token| &token.value)
     }
 
-    fn match_token(&mut self, token_type: char) -> bool {
+    /// Span of the current token, or an empty span just past the last token
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.current_token_index) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or(Span::default(), |token| Span::new(token.span.end, token.span.end)),
+        }
+    }
+
+    /// Returns the current token, with its span, and moves past it.
+    /// Only called once the current token is known to exist.
+    fn take_token(&mut self) -> Token {
+        let token = self.tokens[self.current_token_index].clone();
+        self.advance();
+        token
+    }
+
+    fn match_token(&mut self, token_type: &str) -> bool {
         match self.current_token() {
-            MathValue::Op(op) if *op == token_type => {
+            Some(value) if value.is_op(token_type) => {
                 self.advance();
                 true
             },
             _ => false
         }
+    }
 
+    /// Runs the parse one level deeper, failing once the input nests deeper than `MAX_DEPTH`
+    /// so the recursion can't overflow the stack
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Node, RpnError>) -> Result<Node, RpnError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(RpnError::NestingLimit { span: self.current_span() });
+        }
+        self.depth += 1;
+        let node = parse(self);
+        self.depth -= 1;
+        node
     }
 
-    fn parse_expression(&mut self) -> Node {
-        let mut node = self.parse_term();
-        loop{
-            match self.current_token() {
-                MathValue::Op('+') | MathValue::Op('-') => {
-                    let token = self.current_token().clone();
-                    self.advance();
-                    node = Node::new(token, Some(node), Some(self.parse_term()));
-                },
+    /// Parses a whole expression, which may be a conditional `c ? a : b`.
+    /// Conditionals are right associative, so `a ? b : c ? d : e` picks between b and `c ? d : e`.
+    fn parse_expression(&mut self) -> Result<Node, RpnError> {
+        let condition = self.parse_binary(i16::MAX)?;
+        let span = self.current_span();
+        if !self.match_token("?") {
+            return Ok(condition);
+        }
+        let then = self.nested(Self::parse_expression)?;
+        let colon = self.current_span();
+        if !self.match_token(":") {
+            return Err(RpnError::DanglingOperator { op: "?".to_string(), span });
+        }
+        let otherwise = self.nested(Self::parse_expression)?;
+        let token = Token::new(MathValue::Func(functions::CONDITIONAL.to_string(), 3), span);
+        let mut node = Node::new(token, vec![condition, then, otherwise]);
+        node.separators = vec![span, colon];
+        Ok(node)
+    }
+
+    /// Parses a chain of operators with precedence up to `max_precedence`,
+    /// grouping them by their associativity in the operator table
+    fn parse_binary(&mut self, max_precedence: i16) -> Result<Node, RpnError> {
+        let mut node = self.parse_factor()?;
+        while let Some(MathValue::Op(op)) = self.current_token() {
+            let operator = match self.operators.operator(op) {
+                Some(operator) if operator.arity == 2 && i16::from(operator.precedence) <= max_precedence => operator,
                 _ => break,
-            }
+            };
+            let token = self.take_token();
+            // Right associative operators take the rest of the chain as their right side
+            let right_precedence = match operator.associativity {
+                Associativity::Left => i16::from(operator.precedence) - 1,
+                Associativity::Right => i16::from(operator.precedence),
+            };
+            let right = self.nested(|parser| parser.parse_binary(right_precedence))?;
+            node = Node::new(token, vec![node, right]);
         }
-        node
+        Ok(node)
     }
 
-    fn parse_term(&mut self) -> Node {
-        let mut node = self.parse_expo();
-        loop {
+    fn parse_factor(&mut self) -> Result<Node, RpnError> {
+        let span = self.current_span();
+        if self.match_token("(") {
+            let node = self.nested(Self::parse_expression)?;
+            if !self.match_token(")") {
+                return match self.current_token() {
+                    Some(value) if value.is_op(",") => Err(RpnError::DanglingOperator { op: ",".to_string(), span: self.current_span() }),
+                    _ => Err(RpnError::MismatchedParenthesis { span }),
+                };
+            }
+            Ok(node)
+        } else if let Some(MathValue::Num(_) | MathValue::Alge(_) | MathValue::Const(_) | MathValue::Bool(_)) = self.current_token() {
+            Ok(Node::new(self.take_token(), Vec::new()))
+        } else if let Some(MathValue::Func(..)) = self.current_token() {
+            let function = self.take_token();
+            self.parse_call(function)
+        } else if let Some(operator) = self.current_prefix_operator() {
+            // The operand takes every operator binding tighter than the prefix
+            let token = self.take_token();
+            let operand = self.nested(|parser| parser.parse_binary(i16::from(operator.precedence)))?;
+            Ok(Node::new(token, vec![operand]))
+        } else {
             match self.current_token() {
-                MathValue::Op('*') | MathValue::Op('/') => {
-                    let token = self.current_token().clone();
-                    self.advance();
-                    node = Node::new(token, Some(node), Some(self.parse_expo()));
-                },
-                _ => break,
+                Some(MathValue::Op(op)) if self.operators.has_operator(op) => Err(RpnError::DanglingOperator { op: op.to_string(), span }),
+                _ => Err(RpnError::ExpectedValue { span }),
             }
         }
-        node
     }
 
-    fn parse_expo(&mut self) -> Node {
-        let mut node = self.parse_factor();
-        loop {
-            match self.current_token() {
-                MathValue::Op('^') => {
-                    let token = self.current_token().clone();
-                    self.advance();
-                    node = Node::new(token, Some(node), Some(self.parse_factor()));
-                },
-                _ => break,
+    /// Parses the bracketed, comma separated arguments of a function
+    fn parse_call(&mut self, function: Token) -> Result<Node, RpnError> {
+        let MathValue::Func(name, _) = function.value else {
+            return Err(RpnError::ExpectedValue { span: function.span });
+        };
+        // Functions are only made when a bracket follows the name
+        self.match_token("(");
+        let mut args: Vec<Node> = Vec::new();
+        let mut commas: Vec<Span> = Vec::new();
+        if !self.match_token(")") {
+            loop {
+                args.push(self.nested(Self::parse_expression)?);
+                if self.match_token(")") {
+                    break;
+                }
+                commas.push(self.current_span());
+                if !self.match_token(",") {
+                    return Err(RpnError::MismatchedParenthesis { span: function.span });
+                }
             }
         }
-        node
+        let arity = args.len();
+        if name == functions::CONDITIONAL && arity != 3 {
+            return Err(RpnError::ArityMismatch { name, expected: 3, found: arity, span: function.span });
+        }
+        let mut node = Node::new(Token::new(MathValue::Func(name, arity), function.span), args);
+        node.separators = commas;
+        Ok(node)
     }
 
-    fn parse_factor(&mut self) -> Node {
-        let token = self.current_token().clone();
-        if self.match_token('(') {
-            let node = self.parse_expression();
-            self.match_token(')');
-            node
-        } else if let MathValue::Num(_) | MathValue::Alge(_) = token {
-            self.advance();
-            Node::new(token, None, None)
-        } else {
-            panic!("Unknown Factor");
+    fn current_prefix_operator(&self) -> Option<Operator> {
+        match self.current_token() {
+            Some(MathValue::Op(op)) => self.operators.operator(op).filter(|operator| operator.arity == 1),
+            _ => None,
+        }
+    }
+
+    /// Fails if any tokens were left after parsing the expression
+    fn expect_end(&self) -> Result<(), RpnError> {
+        let span = self.current_span();
+        match self.current_token() {
+            None => Ok(()),
+            Some(value) if value.is_op(")") => Err(RpnError::MismatchedParenthesis { span }),
+            Some(value) if value.is_op(",") => Err(RpnError::DanglingOperator { op: ",".to_string(), span }),
+            Some(_) => {
+                let end = self.tokens.last().map_or(span, |token| token.span);
+                Err(RpnError::LeftoverOperands { span: span.join(end) })
+            },
         }
     }
 
 }
 
+/// Pushes each node after its children, keeping the nodes still to visit on a stack
+/// so a long chain can't overflow the call stack
 fn traverse_tree(node: &Node, stack: &mut Stack) {
-    if let Some(n) = &node.left {
-        traverse_tree(&n, stack);
-    } 
-    if let Some(n) = &node.right {
-        traverse_tree(&n, stack);
+    let mut visits = vec![(node, false)];
+    // The rpn of each finished node whose parent isn't finished yet
+    let mut finished: Vec<Vec<Token>> = Vec::new();
+    while let Some((node, children_finished)) = visits.pop() {
+        if !children_finished {
+            visits.push((node, true));
+            visits.extend(node.children.iter().rev().map(|child| (child, false)));
+            continue;
+        }
+        let mut children = finished.split_off(finished.len() - node.children.len());
+        let is_conditional = matches!(&node.data.value, MathValue::Func(name, _) if name == functions::CONDITIONAL);
+        let rpn = match (is_conditional, &mut children[..]) {
+            // Conditionals jump around each branch rather than following them
+            (true, [condition, then, otherwise]) => {
+                let spans = match node.separators[..] {
+                    [first, second] => (first, second),
+                    _ => (node.data.span, node.data.span),
+                };
+                conditional(std::mem::take(condition), std::mem::take(then), std::mem::take(otherwise), spans)
+            },
+            _ => {
+                let mut children = children.into_iter();
+                let mut rpn = children.next().unwrap_or_default();
+                rpn.extend(children.flatten());
+                rpn.push(node.data.clone());
+                rpn
+            },
+        };
+        finished.push(rpn);
     }
-    match &node.data {
-        MathValue::Alge(_) => stack.push(node.data.clone()),
-        MathValue::Num(_) => stack.push(node.data.clone()),
-        MathValue::Op(_) => stack.push(node.data.clone()),
+    stack.elements.extend(finished.into_iter().flatten());
+}
+
+fn traverse_tree_pre_order(node: &Node, stack: &mut Stack) {
+    let mut pending = vec![node];
+    while let Some(node) = pending.pop() {
+        stack.push(node.data.clone());
+        pending.extend(node.children.iter().rev());
     }
 }
-pub fn convert_in_to_post_fix(input: &str) -> Result<Stack, Box<dyn Error>>{
+pub fn convert_in_to_post_fix(input: &str) -> Result<Stack, RpnError>{
+    convert_in_to_post_fix_with(input, &ParseOptions::default())
+}
+
+/// Converts the input to rpn, reading it with the options
+pub fn convert_in_to_post_fix_with(input: &str, options: &ParseOptions) -> Result<Stack, RpnError> {
     // Uses an post traversal of an ast tree to produce the 
     // rpn
 
-    let mut parser = Parser::try_from(input).unwrap();
+    let mut parser = Parser::try_from(input, options)?;
     let mut rpn= Stack::new();
     
-    let ast = parser.parse_expression();
+    let ast = parser.parse_expression()?;
+    parser.expect_end()?;
     traverse_tree(&ast, &mut rpn);
 
     Ok(rpn)
 }
 
+/// Parses the input into an expression tree
+pub fn parse(input: &str) -> Result<Expr, RpnError> {
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Parses the input into an expression tree, reading it with the options
+pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Expr, RpnError> {
+    let mut parser = Parser::try_from(input, options)?;
+    let ast = parser.parse_expression()?;
+    parser.expect_end()?;
+    into_expr(ast)
+}
+
+/// A node still to convert, or one whose children are converted
+enum Visit {
+    Down(Node),
+    Up(Token, usize),
+}
+
+/// Converts the parsed tree into an expression tree,
+/// keeping the nodes still to convert on a stack so a long chain can't overflow the call stack
+fn into_expr(node: Node) -> Result<Expr, RpnError> {
+    let mut visits = vec![Visit::Down(node)];
+    let mut converted: Vec<Expr> = Vec::new();
+    while let Some(visit) = visits.pop() {
+        match visit {
+            Visit::Down(mut node) => {
+                let children = std::mem::take(&mut node.children);
+                visits.push(Visit::Up(node.data.clone(), children.len()));
+                visits.extend(children.into_iter().rev().map(Visit::Down));
+            },
+            Visit::Up(data, arity) => {
+                let children = converted.split_off(converted.len() - arity);
+                converted.push(into_expr_node(data, children)?);
+            },
+        }
+    }
+    // The root is the only node left
+    let Some(expr) = converted.pop() else { unreachable!() };
+    Ok(expr)
+}
+
+/// Converts one node of the parsed tree, given its children already converted
+fn into_expr_node(data: Token, children: Vec<Expr>) -> Result<Expr, RpnError> {
+    let span = data.span;
+    let mut children = children.into_iter();
+    let expr = match (data.value, children.next(), children.next()) {
+        (MathValue::Num(value), _, _) => Expr::Number(value),
+        // ' ! ' and ' ¬ ' are glued onto the name as '-'
+        (MathValue::Alge(text), _, _) => match text.strip_prefix('-') {
+            Some(name) => Expr::unary(NEGATE, Expr::variable(name)),
+            None => Expr::Variable(text),
+        },
+        (MathValue::Const(name), _, _) => Expr::Variable(name),
+        (MathValue::Bool(value), _, _) => Expr::Bool(value),
+        (MathValue::Func(name, _), first, second) => {
+            Expr::Call { name, args: first.into_iter().chain(second).chain(children).collect() }
+        },
+        (MathValue::Op(op), Some(left), Some(right)) => Expr::binary(&op, left, right),
+        (MathValue::Op(op), Some(operand), None) => Expr::unary(&op, operand),
+        // Operators always have operands once parsed
+        (MathValue::Op(op), None, _) => return Err(RpnError::DanglingOperator { op, span }),
+        // Jumps are only made when writing rpn
+        (MathValue::JumpUnless(_), _, _) => return Err(RpnError::InvalidOperator { op: "?".to_string(), span }),
+        (MathValue::Jump(_), _, _) => return Err(RpnError::InvalidOperator { op: ":".to_string(), span }),
+    };
+    Ok(expr)
+}
+
+/// Converts to prefix (polish notation) using a pre-order traversal of the ast tree
+pub fn convert_in_to_pre_fix(input: &str) -> Result<Stack, RpnError> {
+    let options = ParseOptions::default();
+    let mut parser = Parser::try_from(input, &options)?;
+    let mut prefix = Stack::new();
+
+    let ast = parser.parse_expression()?;
+    parser.expect_end()?;
+    traverse_tree_pre_order(&ast, &mut prefix);
+
+    Ok(prefix)
+}
+
 
 #[cfg(test)]
 mod parser_tests {
@@ -158,10 +375,51 @@ mod parser_tests {
     }        
 }
 
+#[cfg(test)]
+mod parser_error_tests {
+    use super::*;
+    #[test]
+    fn operator_in_value_position() {
+        let error = convert_in_to_post_fix("3+(*2)").unwrap_err();
+        assert_eq!(RpnError::DanglingOperator { op: "*".to_string(), span: Span::new(3, 4) }, error);
+    }
+    #[test]
+    fn empty_brackets() {
+        let error = convert_in_to_post_fix("()+1").unwrap_err();
+        assert_eq!(RpnError::ExpectedValue { span: Span::new(1, 2) }, error);
+    }
+    #[test]
+    fn missing_value_at_end() {
+        let error = convert_in_to_post_fix("3 + 4 *").unwrap_err();
+        assert_eq!(RpnError::DanglingOperator { op: "*".to_string(), span: Span::new(6, 7) }, error);
+    }
+    #[test]
+    fn nesting_limit() {
+        let brackets = |depth: usize| "(".repeat(depth) + "1" + &")".repeat(depth);
+        assert!(convert_in_to_post_fix(&brackets(MAX_DEPTH)).is_ok());
+        let error = convert_in_to_post_fix(&brackets(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(RpnError::NestingLimit { span: Span::new(MAX_DEPTH + 1, MAX_DEPTH + 2) }, error);
+        // Right associative chains nest, left associative ones only grow the tree
+        assert!(matches!(parse(&vec!["x"; 10000].join("^")), Err(RpnError::NestingLimit { .. })));
+        assert!(matches!(parse(&("-".repeat(3000) + "1")), Err(RpnError::NestingLimit { .. })));
+        assert!(matches!(parse(&("a ? b : ".repeat(3000) + "c")), Err(RpnError::NestingLimit { .. })));
+    }
+    #[test]
+    fn long_chains() {
+        let sum = vec!["x"; 10000].join(" + ");
+        assert_eq!(String::from("x") + &" x +".repeat(9999), convert_in_to_post_fix(&sum).unwrap().as_string());
+        assert_eq!("+ ".repeat(9999) + &vec!["x"; 10000].join(" "), convert_in_to_pre_fix(&sum).unwrap().as_string());
+        let expr = parse(&sum).unwrap();
+        assert_eq!(expr.clone(), expr);
+        assert_eq!("10000 * x", simplify::simplify(&expr).to_string());
+        assert_eq!(Expr::Number(10000.0), simplify::simplify(&derivative::differentiate(&expr, "x").unwrap()));
+    }
+}
+
 #[cfg(test)]
 mod ast_tree_tests {
     use super::*;
-    use ast_tree::convert_in_to_post_fix;
+    use ast_tree::{convert_in_to_post_fix, convert_in_to_post_fix_with};
     use rpn_convert_unit_tests::*;
 
     #[test]
@@ -183,5 +441,60 @@ mod ast_tree_tests {
     #[test]
     fn test_alge_complex() {
         alge_complex(convert_in_to_post_fix);
-    }        
+    }
+    #[test]
+    fn test_unary() {
+        unary(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_functions() {
+        functions(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_constants() {
+        constants(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_modulo() {
+        modulo(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_implicit_multiplication() {
+        implicit_multiplication(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_identifiers() {
+        identifiers(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_number_literals() {
+        number_literals(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_custom_operators() {
+        custom_operators(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_multi_char_operators() {
+        multi_char_operators(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_comparisons() {
+        comparisons(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_conditionals() {
+        conditionals(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_associativity() {
+        associativity(convert_in_to_post_fix);
+    }
+    #[test]
+    fn pre_order() {
+        assert_eq!("* + 31 321 + 32 54", convert_in_to_pre_fix("(31 + 321)*(32+54)").unwrap().as_string());
+        assert_eq!("^ 2 ^ 3 2", convert_in_to_pre_fix("2^3^2").unwrap().as_string());
+        assert_eq!("- - 8 4 2", convert_in_to_pre_fix("8-4-2").unwrap().as_string());
+        assert_eq!("~ max x * 2 ~ y", convert_in_to_pre_fix("-max(x, 2*-y)").unwrap().as_string());
+    }
 }
\ No newline at end of file
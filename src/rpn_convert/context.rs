@@ -0,0 +1,225 @@
+use super::*;
+use std::sync::Arc;
+
+lazy_static! {
+    static ref builtin_constants: HashMap<&'static str, f64> = HashMap::from([
+        ("pi", std::f64::consts::PI),
+        ("e", std::f64::consts::E),
+        ("tau", std::f64::consts::TAU),
+        ("phi", 1.618_033_988_749_895),
+    ]);
+}
+
+/// Value of a built-in constant, recognised by the tokenizer
+pub fn builtin_constant(name: &str) -> Option<f64> {
+    builtin_constants.get(name).copied()
+}
+
+/// How % and // round when an operand is negative
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModuloMode {
+    /// Quotient rounds towards zero, the remainder takes the sign of the dividend.
+    /// -7 % 3 = -1 and -7 // 3 = -2
+    #[default]
+    Truncated,
+    /// Quotient rounds down, the remainder takes the sign of the divisor.
+    /// -7 % 3 = 2 and -7 // 3 = -3
+    Floored,
+}
+
+/// How deeply calls to defined functions can nest unless the context says otherwise
+pub const DEFAULT_RECURSION_LIMIT: usize = 64;
+
+/// A function defined with `Context::define`, kept as the rpn of its body
+#[derive(Debug, Clone)]
+pub struct Function {
+    params: Vec<String>,
+    body: Stack,
+}
+impl Function {
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Stack {
+        &self.body
+    }
+}
+
+/// Names and values available while evaluating an expression.
+/// Starts with the built-in constants; variables shadow constants of the same name.
+#[derive(Debug, Clone)]
+pub struct Context {
+    variables: HashMap<String, f64>,
+    constants: HashMap<String, f64>,
+    functions: HashMap<String, Function>,
+    recursion_limit: usize,
+    modulo_mode: ModuloMode,
+    operators: Arc<OperatorTable>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context::new()
+    }
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            variables: HashMap::new(),
+            constants: builtin_constants.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+            functions: HashMap::new(),
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            modulo_mode: ModuloMode::default(),
+            operators: Arc::clone(&operators::default_operators),
+        }
+    }
+
+    /// Context holding the built-in constants and the given variables
+    pub fn with_variables(variables: &HashMap<String, f64>) -> Context {
+        let mut context = Context::new();
+        context.variables = variables.clone();
+        context
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: f64) -> &mut Context {
+        self.variables.insert(name.to_string(), value);
+        self
+    }
+
+    /// Registers a constant, replacing any constant with the same name
+    pub fn set_constant(&mut self, name: &str, value: f64) -> &mut Context {
+        self.constants.insert(name.to_string(), value);
+        self
+    }
+
+    /// Chooses how % and // treat negative operands
+    pub fn set_modulo_mode(&mut self, mode: ModuloMode) -> &mut Context {
+        self.modulo_mode = mode;
+        self
+    }
+
+    pub fn modulo_mode(&self) -> ModuloMode {
+        self.modulo_mode
+    }
+
+    /// Calculates operators with this table, which should be the one the input was read with
+    pub fn set_operators(&mut self, operators: OperatorTable) -> &mut Context {
+        self.operators = Arc::new(operators);
+        self
+    }
+
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
+
+    /// Defines a function from text such as `f(x, y) = x^2 + y`, replacing any
+    /// function with the same name. The body is checked now but only solved when
+    /// the function is called, taking its parameters from the call and any other
+    /// name from the context at that time. Built-in functions can't be redefined.
+    /// Spans of errors in the body point into the definition.
+    pub fn define(&mut self, definition: &str) -> Result<&mut Context, RpnError> {
+        self.define_with(definition, &ParseOptions::default())
+    }
+
+    /// Defines a function, reading its body with the options
+    pub fn define_with(&mut self, definition: &str, options: &ParseOptions) -> Result<&mut Context, RpnError> {
+        let (head, body) = definition.split_once('=')
+            .ok_or(RpnError::InvalidDefinition { span: Span::new(0, definition.len()) })?;
+        let invalid = RpnError::InvalidDefinition { span: Span::new(0, head.len()) };
+        let (name, params) = head.trim().strip_suffix(')')
+            .and_then(|head| head.split_once('('))
+            .ok_or(invalid.clone())?;
+        let name = name.trim();
+        let params: Vec<String> = match params.trim() {
+            "" => Vec::new(),
+            params => params.split(',').map(|param| param.trim().to_string()).collect(),
+        };
+        let distinct = params.iter().enumerate().all(|(index, param)| !params[..index].contains(param));
+        let reserved = functions::builtin(name).is_some() || name == functions::CONDITIONAL;
+        if !is_identifier(name) || reserved || !distinct || !params.iter().all(|param| is_identifier(param)) {
+            return Err(invalid);
+        }
+        // Blanking out the head keeps every span pointing into the definition
+        let body_input = format!("{}{}", " ".repeat(head.len() + 1), body);
+        if let Some(error) = Validate::validate_all_with(&body_input, options).into_iter().next() {
+            return Err(error);
+        }
+        let body = shunting_yard::convert_in_to_post_fix_with(&body_input, options)?;
+        self.functions.insert(name.to_string(), Function { params, body });
+        Ok(self)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Names of every defined function, sorted
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Limits how deeply calls to defined functions can nest, so a function
+    /// that always calls itself fails with `RpnError::RecursionLimit`
+    pub fn set_recursion_limit(&mut self, limit: usize) -> &mut Context {
+        self.recursion_limit = limit;
+        self
+    }
+
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    pub fn constant(&self, name: &str) -> Option<f64> {
+        self.constants.get(name).copied()
+    }
+
+    /// Value of the name as a variable, or failing that as a constant
+    pub fn value_of(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).or_else(|| self.constants.get(name)).copied()
+    }
+}
+
+#[cfg(test)]
+mod context_tests {
+    use super::*;
+
+    #[test]
+    fn builtin_constants() {
+        let context = Context::new();
+        assert_eq!(Some(std::f64::consts::PI), context.value_of("pi"));
+        assert_eq!(Some(std::f64::consts::TAU), context.constant("tau"));
+        assert_eq!(None, context.value_of("x"));
+    }
+    #[test]
+    fn variables_shadow_constants() {
+        let mut context = Context::new();
+        context.set_constant("g", 9.81).set_variable("e", 0.5);
+        assert_eq!(Some(9.81), context.value_of("g"));
+        assert_eq!(Some(0.5), context.value_of("e"));
+        assert_eq!(Some(std::f64::consts::E), context.constant("e"));
+    }
+    #[test]
+    fn defines_functions() {
+        let mut context = Context::new();
+        context.define("f(x, y) = x^2 + y").unwrap().define("id(x)=x").unwrap().define("two() = 2").unwrap();
+        assert_eq!(["x", "y"], context.function("f").unwrap().params());
+        assert_eq!("x 2 ^ y +", context.function("f").unwrap().body().as_string());
+        assert_eq!("x", context.function("id").unwrap().body().as_string());
+        assert!(context.function("two").unwrap().params().is_empty());
+    }
+    #[test]
+    fn invalid_definitions() {
+        let mut context = Context::new();
+        for definition in ["f(x) x + 1", "f x = x", "f(x, x) = x", "f(2) = 2", "sin(x) = x", "if(a, b, c) = a", "2f(x) = x", "f(x)y = x"] {
+            assert!(matches!(context.define(definition), Err(RpnError::InvalidDefinition { .. })), "{}", definition);
+        }
+        let error = context.define("f(x) = x + * 2").unwrap_err();
+        assert_eq!(RpnError::DanglingOperator { op: "*".to_string(), span: Span::new(11, 12) }, error);
+        assert!(context.define("f(x) = ").is_err());
+        assert!(context.function("f").is_none());
+    }
+}
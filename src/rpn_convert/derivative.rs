@@ -0,0 +1,272 @@
+use super::*;
+
+/// Differentiates the expression with respect to the variable, using the sum, product,
+/// quotient, power and chain rules. Every other name is treated as a constant.
+/// Terms that are obviously 0 or 1 are left out as the result is built,
+/// anything further is up to simplification.
+pub fn differentiate(expr: &Expr, variable: &str) -> Result<Expr, RpnError> {
+    expr.fold(&mut Derive(variable)).derivative()
+}
+
+/// A part of the expression with its derivative, None if it doesn't depend on the variable.
+/// Failures are kept with the part, as the condition of a conditional is never differentiated.
+#[derive(Clone)]
+struct Term {
+    expr: Expr,
+    derivative: Option<Result<Expr, RpnError>>,
+}
+
+impl Term {
+    fn constant(expr: Expr) -> Term {
+        Term { expr, derivative: None }
+    }
+
+    fn derivative(&self) -> Result<Expr, RpnError> {
+        Ok(self.derivative.clone().transpose()?.unwrap_or(Expr::Number(0.0)))
+    }
+}
+
+/// Differentiates each part of the expression from the derivatives of its operands
+struct Derive<'a>(&'a str);
+impl Fold for Derive<'_> {
+    type Output = Term;
+
+    fn number(&mut self, value: f64) -> Term {
+        Term::constant(Expr::Number(value))
+    }
+    fn boolean(&mut self, value: bool) -> Term {
+        Term::constant(Expr::Bool(value))
+    }
+    fn variable(&mut self, name: &str) -> Term {
+        Term { expr: Expr::variable(name), derivative: (name == self.0).then_some(Ok(Expr::Number(1.0))) }
+    }
+    fn binary(&mut self, op: &str, left: Term, right: Term) -> Term {
+        let derivative = match (left.derivative, right.derivative) {
+            (None, None) => None,
+            (du, dv) => Some(binary(op, &left.expr, du, &right.expr, dv)),
+        };
+        Term { expr: Expr::binary(op, left.expr, right.expr), derivative }
+    }
+    fn unary(&mut self, op: &str, operand: Term) -> Term {
+        let derivative = operand.derivative.map(|derivative| match op == NEGATE {
+            true => derivative.map(neg),
+            false => Err(not_differentiable(op)),
+        });
+        Term { expr: Expr::unary(op, operand.expr), derivative }
+    }
+    fn call(&mut self, name: &str, args: Vec<Term>) -> Term {
+        let derivative = match args.iter().any(|arg| arg.derivative.is_some()) {
+            true => Some(self.call_derivative(name, &args)),
+            false => None,
+        };
+        Term { expr: Expr::call(name, args.into_iter().map(|arg| arg.expr).collect()), derivative }
+    }
+}
+
+fn binary(op: &str, u: &Expr, du: Option<Result<Expr, RpnError>>, v: &Expr, dv: Option<Result<Expr, RpnError>>) -> Result<Expr, RpnError> {
+    let (u_depends, v_depends) = (du.is_some(), dv.is_some());
+    let du = du.transpose()?.unwrap_or(Expr::Number(0.0));
+    let dv = dv.transpose()?.unwrap_or(Expr::Number(0.0));
+    // Only the rules using the operands themselves copy them
+    let (u, v) = (|| u.clone(), || v.clone());
+    match op {
+        "+" => Ok(add(du, dv)),
+        "-" => Ok(sub(du, dv)),
+        "*" => Ok(add(mul(du, v()), mul(u(), dv))),
+        "/" if !v_depends => Ok(div(du, v())),
+        "/" => Ok(div(sub(mul(du, v()), mul(u(), dv)), pow(v(), Expr::Number(2.0)))),
+        // Power rule, n * u^(n-1) * u'
+        "^" if !v_depends => {
+            let lowered = match v() {
+                Expr::Number(n) => Expr::Number(n - 1.0),
+                v => sub(v, Expr::Number(1.0)),
+            };
+            Ok(mul(mul(v(), pow(u(), lowered)), du))
+        },
+        // Exponential rule, a^v * ln(a) * v'
+        "^" if !u_depends => Ok(mul(mul(Expr::binary(op, u(), v()), Expr::call("ln", vec![u()])), dv)),
+        // u^v * (v' * ln(u) + v * u' / u)
+        "^" => {
+            let inner = add(mul(dv, Expr::call("ln", vec![u()])), div(mul(v(), du), u()));
+            Ok(mul(Expr::binary(op, u(), v()), inner))
+        },
+        _ => Err(not_differentiable(op)),
+    }
+}
+
+impl Derive<'_> {
+    fn call_derivative(&mut self, name: &str, args: &[Term]) -> Result<Expr, RpnError> {
+        // Each branch is differentiated on its own, the condition only picks one
+        if let (functions::CONDITIONAL, [condition, then, otherwise]) = (name, args) {
+            return Ok(Expr::call(name, vec![condition.expr.clone(), then.derivative()?, otherwise.derivative()?]));
+        }
+        let function = functions::builtin(name)
+            .ok_or_else(|| RpnError::UnknownFunction { name: name.to_string(), span: Span::default() })?;
+        if function.arity != args.len() {
+            return Err(RpnError::ArityMismatch {
+                name: name.to_string(), expected: function.arity, found: args.len(), span: Span::default()
+            });
+        }
+        let one = || Expr::Number(1.0);
+        let square = |arg: &Expr| pow(arg.clone(), Expr::Number(2.0));
+        let a = args[0].expr.clone();
+        // Derivative of the outer function at its argument, to be multiplied by the inner derivative
+        let outer = match name {
+            "sin" => Expr::call("cos", vec![a.clone()]),
+            "cos" => neg(Expr::call("sin", vec![a.clone()])),
+            "tan" => div(one(), pow(Expr::call("cos", vec![a.clone()]), Expr::Number(2.0))),
+            "asin" => div(one(), Expr::call("sqrt", vec![sub(one(), square(&a))])),
+            "acos" => neg(div(one(), Expr::call("sqrt", vec![sub(one(), square(&a))]))),
+            "atan" => div(one(), add(one(), square(&a))),
+            "sqrt" => div(one(), mul(Expr::Number(2.0), Expr::call("sqrt", vec![a.clone()]))),
+            "abs" => div(a.clone(), Expr::call("abs", vec![a.clone()])),
+            "ln" => div(one(), a.clone()),
+            "log10" => div(one(), mul(a.clone(), Expr::call("ln", vec![Expr::Number(10.0)]))),
+            "exp" => Expr::call("exp", vec![a.clone()]),
+            // Steps, flat everywhere they are defined
+            "floor" | "ceil" | "round" => Expr::Number(0.0),
+            // Rewritten in terms of functions with their own rules
+            "atan2" => {
+                let (y, x) = (&args[0], &args[1]);
+                let numerator = sub(mul(x.expr.clone(), y.derivative()?), mul(y.expr.clone(), x.derivative()?));
+                return Ok(div(numerator, add(square(&x.expr), square(&y.expr))));
+            },
+            "log" => {
+                let value = self.call("ln", vec![args[1].clone()]);
+                let base = self.call("ln", vec![args[0].clone()]);
+                return self.binary("/", value, base).derivative();
+            },
+            "min" | "max" => {
+                let (a, b) = (&args[0], &args[1]);
+                let sum = self.binary("+", a.clone(), b.clone());
+                let difference = self.binary("-", a.clone(), b.clone());
+                let distance = self.call("abs", vec![difference]);
+                let doubled = self.binary(if name == "max" { "+" } else { "-" }, sum, distance);
+                return self.binary("/", doubled, Term::constant(Expr::Number(2.0))).derivative();
+            },
+            _ => return Err(not_differentiable(name)),
+        };
+        Ok(chain(outer, args[0].derivative()?))
+    }
+}
+
+fn not_differentiable(name: &str) -> RpnError {
+    RpnError::NotDifferentiable { name: name.to_string(), span: Span::default() }
+}
+
+fn is_number(expr: &Expr, value: f64) -> bool {
+    matches!(expr, Expr::Number(n) if *n == value)
+}
+
+/// Multiplies the outer derivative by the inner one, moving it above any `1 /`
+fn chain(outer: Expr, inner: Expr) -> Expr {
+    match outer {
+        Expr::Binary { op, left, right } if op == "/" && is_number(&left, 1.0) => div(inner, *right),
+        outer => mul(outer, inner),
+    }
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    match (is_number(&a, 0.0), is_number(&b, 0.0)) {
+        (true, _) => b,
+        (_, true) => a,
+        _ => Expr::binary("+", a, b),
+    }
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    match (is_number(&a, 0.0), is_number(&b, 0.0)) {
+        (_, true) => a,
+        (true, _) => neg(b),
+        _ => Expr::binary("-", a, b),
+    }
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    if is_number(&a, 0.0) || is_number(&b, 0.0) {
+        return Expr::Number(0.0);
+    }
+    match (is_number(&a, 1.0), is_number(&b, 1.0)) {
+        (true, _) => b,
+        (_, true) => a,
+        _ => Expr::binary("*", a, b),
+    }
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    match (is_number(&a, 0.0), is_number(&b, 1.0)) {
+        (true, _) => Expr::Number(0.0),
+        (_, true) => a,
+        _ => Expr::binary("/", a, b),
+    }
+}
+
+fn pow(a: Expr, b: Expr) -> Expr {
+    match is_number(&b, 1.0) {
+        true => a,
+        false => Expr::binary("^", a, b),
+    }
+}
+
+fn neg(a: Expr) -> Expr {
+    match a {
+        Expr::Number(n) => Expr::Number(-n),
+        Expr::Unary { op, operand } if op == NEGATE => *operand,
+        a => Expr::unary(NEGATE, a),
+    }
+}
+
+#[cfg(test)]
+mod derivative_tests {
+    use super::*;
+
+    fn derive(input: &str) -> Expr {
+        differentiate(&ast_tree::parse(input).unwrap(), "x").unwrap()
+    }
+
+    fn value_at(expr: &Expr, x: f64) -> f64 {
+        let mut context = Context::new();
+        context.set_variable("x", x).set_variable("y", 0.7);
+        evaluate::evaluate(&expr.to_stack(), &context).unwrap()
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!("2 * x", derive("x^2").to_string());
+        assert_eq!("3 * x ^ 2 + 2", derive("x^3 + 2*x - 7").to_string());
+        assert_eq!("cos(y * x) * y", derive("sin(y * x)").to_string());
+        assert_eq!("2 ^ x * ln(2)", derive("2^x").to_string());
+        assert_eq!("(x + 1 - x) / (x + 1) ^ 2", derive("x / (x + 1)").to_string());
+        assert_eq!("2 * x / x ^ 2", derive("ln(x^2)").to_string());
+        assert_eq!("0", derive("y^2 + pi").to_string());
+        assert_eq!("x > 0 ? 2 * x : 3", derive("x > 0 ? x^2 : 3*x").to_string());
+        assert_eq!(Ok(Expr::Number(0.0)), differentiate(&ast_tree::parse("true ? x : 1").unwrap(), "true"));
+    }
+    #[test]
+    fn printable_as_rpn() {
+        assert_eq!("x sin x x cos * +", derive("x * sin(x)").to_stack().as_string());
+    }
+    /// Checks each derivative against a central difference
+    #[test]
+    fn matches_numerical_slope() {
+        let inputs = [
+            "x^3 - 4*x", "x^x", "sqrt(x) * exp(-x)", "tan(x) / x", "asin(x / 2) + acos(x / 3)",
+            "atan(x^2)", "atan2(y, x)", "atan2(x, y)", "log(2, x) + log10(x)", "abs(x - 2)",
+            "max(x, y)", "min(x^2, y)", "-cos(x)^2", "(x + y)^(1 / x)", "floor(x) + x",
+        ];
+        let h = 1e-6;
+        for input in inputs {
+            let expr = ast_tree::parse(input).unwrap();
+            let slope = (value_at(&expr, 1.3 + h) - value_at(&expr, 1.3 - h)) / (2.0 * h);
+            let exact = value_at(&differentiate(&expr, "x").unwrap(), 1.3);
+            assert!((slope - exact).abs() < 1e-5, "{}: {} vs {}", input, slope, exact);
+        }
+    }
+    #[test]
+    fn undifferentiable() {
+        let expr = ast_tree::parse("x % 2").unwrap();
+        assert_eq!(Err(RpnError::NotDifferentiable { name: "%".to_string(), span: Span::default() }), differentiate(&expr, "x"));
+        let expr = ast_tree::parse("sin(x, 2)").unwrap();
+        assert!(matches!(differentiate(&expr, "x"), Err(RpnError::ArityMismatch { expected: 1, found: 2, .. })));
+    }
+}
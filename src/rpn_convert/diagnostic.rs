@@ -0,0 +1,257 @@
+use super::*;
+
+/// Renders the error under the input it came from, underlining the
+/// offending token with `^~~~` and adding a hint where one is known.
+///
+/// ```text
+/// error: Invalid order of operators: '/'
+///   | 3*/2
+///   |   ^
+///   = help: `/` needs a value on each side
+/// ```
+pub fn render(input: &str, error: &RpnError) -> String {
+    render_with_context(input, error, &Context::new())
+}
+
+/// Renders the error as `render` does, with hints naming the operators
+/// and functions known to the context
+pub fn render_with_context(input: &str, error: &RpnError, context: &Context) -> String {
+    let mut rendered = format!("error: {}\n  | {}\n  | {}", error, input, underline(input, &spans(error)));
+    if let Some(hint) = help_with_context(input, error, context) {
+        rendered.push_str(&format!("\n  = help: {}", hint));
+    }
+    rendered
+}
+
+/// Renders each error in turn, separated by a blank line
+pub fn render_all(input: &str, errors: &[RpnError]) -> String {
+    render_all_with_context(input, errors, &Context::new())
+}
+
+pub fn render_all_with_context(input: &str, errors: &[RpnError], context: &Context) -> String {
+    errors.iter().map(|error| render_with_context(input, error, context)).collect::<Vec<String>>().join("\n\n")
+}
+
+/// A hint on how to fix the error, if one is known
+pub fn help(input: &str, error: &RpnError) -> Option<String> {
+    help_with_context(input, error, &Context::new())
+}
+
+/// A hint on how to fix the error, naming the operators and functions known to the context
+pub fn help_with_context(input: &str, error: &RpnError, context: &Context) -> Option<String> {
+    match error {
+        RpnError::TooShort { .. } => Some("enter a value or an expression, e.g. `x` or `1+2`".to_string()),
+        RpnError::UnknownChar { ch, span } => symbol_hint(&ch.to_string(), next_char(input, *span), context.operators()),
+        RpnError::InvalidOperator { op, span } => symbol_hint(op, next_char(input, *span), context.operators()),
+        RpnError::DanglingOperator { op, .. } => match op.as_str() {
+            NEGATE => Some("negation needs a value after it".to_string()),
+            NOT => Some("`not` needs a comparison after it".to_string()),
+            "," => Some("commas separate the arguments of a function call".to_string()),
+            "?" | ":" => Some("a conditional is written `condition ? a : b`".to_string()),
+            op if arity(op, context) == Some(1) => Some(format!("`{}` needs a value after it", op)),
+            op => Some(format!("`{}` needs a value on each side", op)),
+        },
+        RpnError::ExpectedValue { span } => match next_char(input, Span::new(span.start, span.start)) {
+            Some('!' | '¬') => Some("`!` makes the number or name straight after it negative, use `-` to negate a bracket".to_string()),
+            _ => Some("put a number or variable here".to_string()),
+        },
+        RpnError::MismatchedParenthesis { span } => match input.get(span.start..span.end) {
+            Some(")") => Some("this `)` has no matching `(`".to_string()),
+            _ => Some("this `(` is never closed".to_string()),
+        },
+        RpnError::StackUnderflow { op, .. } => match arity(op, context) {
+            Some(1) => Some(format!("`{}` needs a value", op)),
+            Some(arity) => Some(format!("`{}` needs {} values", op, arity)),
+            None => Some(format!("`{}` needs more values", op)),
+        },
+        RpnError::LeftoverOperands { .. } => Some("put an operator between each pair of values".to_string()),
+        RpnError::UnknownFunction { .. } => {
+            let mut names = functions::builtin_names();
+            names.extend(context.function_names());
+            names.sort_unstable();
+            Some(format!("known functions are {}", names.join(", ")))
+        },
+        RpnError::ArityMismatch { name, expected: 1, .. } => Some(format!("call it as `{}(x)`", name)),
+        RpnError::ArityMismatch { name, expected, .. } => {
+            let args = ["a", "b", "c", "d"].iter().take(*expected).copied().collect::<Vec<&str>>();
+            Some(format!("call it as `{}({})`", name, args.join(", ")))
+        },
+        RpnError::UnboundVariables { .. } => Some("give each variable a value before solving".to_string()),
+        RpnError::NumberParse { .. } => Some("numbers are written like 12, 1.5, .5, 1.5e-3, 1_000, 0x1F, 0b1010 or 0o17".to_string()),
+        RpnError::NotDifferentiable { .. } => Some("only +, -, *, /, ^ and the built-in functions have derivatives".to_string()),
+        RpnError::InvalidDefinition { .. } => {
+            Some("define a function as `f(x, y) = x^2 + y`, with a new name and different parameters".to_string())
+        },
+        RpnError::RecursionLimit { .. } => Some("a function calling itself needs a way to stop".to_string()),
+        RpnError::TypeMismatch { expected: ValueType::Number, .. } => {
+            Some("comparisons give true or false, which can only be combined with and, or and not".to_string())
+        },
+        RpnError::TypeMismatch { expected: ValueType::Bool, .. } => {
+            Some("and, or and not combine comparisons such as `x < 1`".to_string())
+        },
+        RpnError::NestingLimit { .. } => Some(format!("split it into smaller expressions, at most {} levels deep", MAX_DEPTH)),
+    }
+}
+
+/// How many values the operator or function takes, if it is known
+fn arity(op: &str, context: &Context) -> Option<usize> {
+    if op == functions::CONDITIONAL {
+        return Some(3);
+    }
+    context.operators().operator(op).map(|operator| operator.arity)
+        .or_else(|| functions::builtin(op).map(|function| function.arity))
+        .or_else(|| context.function(op).map(|function| function.params().len()))
+}
+
+/// A hint for a symbol that is not an operator, suggesting the one most likely meant
+fn symbol_hint(symbol: &str, next: Option<char>, operators: &OperatorTable) -> Option<String> {
+    let hint = match (symbol, next) {
+        ("=", Some('<')) => "did you mean `<=`?",
+        ("=", Some('>')) => "did you mean `>=`?",
+        ("=", _) => "did you mean `==` to compare? `=` only defines functions",
+        ("&", _) => "did you mean `and`?",
+        ("|", _) => "did you mean `or`?",
+        ("×" | "·", _) => "did you mean `*`?",
+        ("÷", _) => "did you mean `/`?",
+        ("[" | "]" | "{" | "}", _) => "use `(` and `)` for grouping",
+        (",", _) => "use `.` for decimals, commas only separate function arguments",
+        _ => return Some(format!("acceptable operators are {}", operators.written_symbols().join(", "))),
+    };
+    Some(hint.to_string())
+}
+
+/// The char straight after the span
+fn next_char(input: &str, span: Span) -> Option<char> {
+    input.get(span.end..).and_then(|rest| rest.chars().next())
+}
+
+/// Every span the error points at
+fn spans(error: &RpnError) -> Vec<Span> {
+    match error {
+        RpnError::UnboundVariables { variables } => variables.iter().map(|(_, span)| *span).collect(),
+        _ => vec![error.span()],
+    }
+}
+
+/// Line of `^~~~` markers under each span, aligned by char
+fn underline(input: &str, spans: &[Span]) -> String {
+    let mut line: Vec<char> = Vec::new();
+    for span in spans {
+        // Spans past the end of the input point just after it
+        let start = char_column(input, span.start);
+        let width = char_column(input, span.end).saturating_sub(start).max(1);
+        if line.len() < start + width {
+            line.resize(start + width, ' ');
+        }
+        line[start] = '^';
+        for marker in line.iter_mut().skip(start + 1).take(width - 1) {
+            *marker = '~';
+        }
+    }
+    line.into_iter().collect::<String>().trim_end().to_string()
+}
+
+/// Converts a byte offset to a column counted in chars
+fn char_column(input: &str, byte_offset: usize) -> usize {
+    input.char_indices().take_while(|(index, _)| *index < byte_offset).count()
+}
+
+#[cfg(test)]
+mod diagnostic_tests {
+    use super::*;
+
+    #[test]
+    fn underlines_token() {
+        let input = "12 + 4 @ 2";
+        let error = Validate::validate_input(input).unwrap_err();
+        let expected = "error: Invalid Char: '@'\n  | 12 + 4 @ 2\n  |        ^\n  = help: acceptable operators are ^, **, %, *, /, //, +, -, <, >, !=, <=, ==, >=, not, and, or";
+        assert_eq!(expected, render(input, &error));
+    }
+    #[test]
+    fn trailing_operator() {
+        let input = "3 + 4 *";
+        let error = Validate::validate_input(input).unwrap_err();
+        let expected = "error: Invalid order of operators: '*'\n  | 3 + 4 *\n  |       ^\n  = help: `*` needs a value on each side";
+        assert_eq!(expected, render(input, &error));
+    }
+    #[test]
+    fn integer_division_symbol() {
+        let input = "7 // ";
+        let error = Validate::validate_input(input).unwrap_err();
+        let expected = "error: Invalid order of operators: '//'\n  | 7 // \n  |   ^~\n  = help: `//` needs a value on each side";
+        assert_eq!(expected, render(input, &error));
+    }
+    fn hint(input: &str) -> Option<String> {
+        help(input, &Validate::validate_input(input).unwrap_err())
+    }
+
+    #[test]
+    fn suggests_comparisons() {
+        assert_eq!(Some("did you mean `==` to compare? `=` only defines functions".to_string()), hint("x = 1"));
+        assert_eq!(Some("did you mean `<=`?".to_string()), hint("x =< 1"));
+        assert_eq!(Some("did you mean `>=`?".to_string()), hint("x => 1"));
+    }
+    #[test]
+    fn suggests_word_operators() {
+        assert_eq!(Some("did you mean `and`?".to_string()), hint("a & b"));
+        assert_eq!(Some("did you mean `and`?".to_string()), hint("a && b"));
+        assert_eq!(Some("did you mean `or`?".to_string()), hint("a | b"));
+        assert_eq!(Some("did you mean `or`?".to_string()), hint("a || b"));
+    }
+    #[test]
+    fn suggests_arithmetic() {
+        assert_eq!(Some("did you mean `*`?".to_string()), hint("2 × 3"));
+        assert_eq!(Some("did you mean `/`?".to_string()), hint("6 ÷ 3"));
+    }
+    #[test]
+    fn negative_bracket() {
+        let input = "2 * !(x + 1)";
+        let error = Validate::validate_input(input).unwrap_err();
+        assert_eq!(RpnError::ExpectedValue { span: Span::new(4, 5) }, error);
+        let expected = "`!` makes the number or name straight after it negative, use `-` to negate a bracket";
+        assert_eq!(Some(expected.to_string()), help(input, &error));
+        assert_eq!(Some(expected.to_string()), hint("¬(x) + 1"));
+    }
+    #[test]
+    fn renders_every_validation_error() {
+        let input = "(1 +* 2";
+        let expected = "error: Invalid order of operators: '*'\n  | (1 +* 2\n  |     ^\n  = help: `*` needs a value on each side\n\n\
+            error: Invalid order of parentheses\n  | (1 +* 2\n  | ^\n  = help: this `(` is never closed";
+        assert_eq!(expected, render_all(input, &Validate::validate_all(input)));
+    }
+    #[test]
+    fn hints_follow_the_context() {
+        let mut operators = OperatorTable::new();
+        operators.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1])).unwrap();
+        operators.add("$", Operator::prefix(1), |args| args[0] * 100.0).unwrap();
+        let mut context = Context::new();
+        context.set_operators(operators.clone()).define("area(w, h) = w * h").unwrap();
+        let mut options = ParseOptions::new();
+        options.set_operators(operators);
+        let unknown = Validate::validate_input("1 @ 2").unwrap_err();
+        let expected = "acceptable operators are ^, **, $, %, *, /, |, //, +, -, <, >, !=, <=, ==, >=, not, and, or";
+        assert_eq!(Some(expected.to_string()), help_with_context("1 @ 2", &unknown, &context));
+
+        let underflow = |rpn: &str| {
+            let error = crate::solve_rpn_with_context(rpn, &context).unwrap_err();
+            help_with_context(rpn, &error, &context)
+        };
+        assert_eq!(Some("`area` needs 2 values".to_string()), underflow("1 area"));
+        assert_eq!(Some("`max` needs 2 values".to_string()), underflow("1 max"));
+        assert_eq!(Some("`$` needs a value".to_string()), underflow("$"));
+        assert_eq!(Some("`|` needs 2 values".to_string()), underflow("1 |"));
+
+        let dangling = Validate::validate_input_with("1 $", &options).unwrap_err();
+        assert_eq!(Some("`$` needs a value after it".to_string()), help_with_context("1 $", &dangling, &context));
+
+        let missing = RpnError::UnknownFunction { name: "volume".to_string(), span: Span::new(0, 6) };
+        assert!(help_with_context("volume(1)", &missing, &context).unwrap().contains(", area, "));
+        assert!(!help("volume(1)", &missing).unwrap().contains("area"));
+    }
+    #[test]
+    fn underlines_every_unbound_variable() {
+        let input = "rate * ¬x + 1";
+        let error = crate::solve_with_variables(input, &HashMap::new()).unwrap_err();
+        assert_eq!("^~~~   ^~", underline(input, &spans(&error)));
+    }
+}
@@ -0,0 +1,150 @@
+use super::*;
+
+/// Byte offsets of a token in the original input, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Span of a single char starting at the given byte offset
+    pub fn of_char(start: usize, ch: char) -> Span {
+        Span::new(start, start + ch.len_utf8())
+    }
+
+    /// Smallest span covering both spans
+    pub fn join(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Every way converting or solving an expression can fail.
+/// Each variant carries the span of the offending input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpnError {
+    /// Nothing but spaces was entered
+    TooShort { span: Span },
+    /// A char that is not a digit, letter, operator or bracket
+    UnknownChar { ch: char, span: Span },
+    /// A symbol in operator position that is not a known operator
+    InvalidOperator { op: String, span: Span },
+    /// An operator without an operand on one side
+    DanglingOperator { op: String, span: Span },
+    /// A value was expected but something else, or nothing, was found
+    ExpectedValue { span: Span },
+    /// A bracket without its partner
+    MismatchedParenthesis { span: Span },
+    /// An operator or function with fewer operands on the stack than it needs
+    StackUnderflow { op: String, span: Span },
+    /// Operands left on the stack after the last operator
+    LeftoverOperands { span: Span },
+    /// A call to a function that does not exist
+    UnknownFunction { name: String, span: Span },
+    /// A function called with the wrong number of arguments
+    ArityMismatch { name: String, expected: usize, found: usize, span: Span },
+    /// Variables without a value, in order of first use
+    UnboundVariables { variables: Vec<(String, Span)> },
+    /// A number that could not be parsed
+    NumberParse { text: String, span: Span },
+    /// An operator or function without a derivative rule
+    NotDifferentiable { name: String, span: Span },
+    /// A function definition not written as `name(a, b) = body`
+    InvalidDefinition { span: Span },
+    /// Calls to defined functions nested deeper than the context allows
+    RecursionLimit { name: String, span: Span },
+    /// A number where true or false was needed, or the other way around
+    TypeMismatch { expected: ValueType, span: Span },
+    /// An expression nested deeper than `MAX_DEPTH`
+    NestingLimit { span: Span },
+}
+
+impl RpnError {
+    /// Span of the input that caused the error.
+    /// For unbound variables this is the first unbound variable.
+    pub fn span(&self) -> Span {
+        match self {
+            RpnError::TooShort { span } |
+            RpnError::UnknownChar { span, .. } |
+            RpnError::InvalidOperator { span, .. } |
+            RpnError::DanglingOperator { span, .. } |
+            RpnError::ExpectedValue { span } |
+            RpnError::MismatchedParenthesis { span } |
+            RpnError::StackUnderflow { span, .. } |
+            RpnError::LeftoverOperands { span } |
+            RpnError::UnknownFunction { span, .. } |
+            RpnError::ArityMismatch { span, .. } |
+            RpnError::NumberParse { span, .. } |
+            RpnError::NotDifferentiable { span, .. } |
+            RpnError::InvalidDefinition { span } |
+            RpnError::RecursionLimit { span, .. } |
+            RpnError::TypeMismatch { span, .. } |
+            RpnError::NestingLimit { span } => *span,
+            RpnError::UnboundVariables { variables } => {
+                variables.first().map(|(_, span)| *span).unwrap_or_default()
+            },
+        }
+    }
+}
+
+impl fmt::Display for RpnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpnError::TooShort { .. } => write!(f, "Enter at least one value"),
+            RpnError::UnknownChar { ch, .. } => write!(f, "Invalid Char: '{}'", ch),
+            RpnError::InvalidOperator { op, .. } => write!(f, "Invalid operator: '{}'", op),
+            RpnError::DanglingOperator { op, .. } => write!(f, "Invalid order of operators: '{}'", op),
+            RpnError::ExpectedValue { .. } => write!(f, "Expected a value"),
+            RpnError::MismatchedParenthesis { .. } => write!(f, "Invalid order of parentheses"),
+            RpnError::StackUnderflow { op, .. } => write!(f, "Not enough operands for '{}'", op),
+            RpnError::LeftoverOperands { .. } => write!(f, "Operands left without an operator"),
+            RpnError::UnknownFunction { name, .. } => write!(f, "Unknown function: '{}'", name),
+            RpnError::ArityMismatch { name, expected, found, .. } => {
+                write!(f, "'{}' takes {} arguments but {} were given", name, expected, found)
+            },
+            RpnError::UnboundVariables { variables } => {
+                let names = variables.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>();
+                write!(f, "Unbound variables: {}", names.join(", "))
+            },
+            RpnError::NumberParse { text, .. } => write!(f, "Invalid number: '{}'", text),
+            RpnError::NotDifferentiable { name, .. } => write!(f, "Cannot differentiate '{}'", name),
+            RpnError::InvalidDefinition { .. } => write!(f, "Invalid function definition"),
+            RpnError::RecursionLimit { name, .. } => write!(f, "Calls to '{}' nested too deeply", name),
+            RpnError::TypeMismatch { expected, .. } => write!(f, "Expected {}", expected),
+            RpnError::NestingLimit { .. } => write!(f, "Expression nested too deeply"),
+        }
+    }
+}
+
+impl Error for RpnError {}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn spans_point_into_input() {
+        let error = Stack::try_from("3 + 4 @ 2").unwrap_err();
+        assert_eq!(RpnError::UnknownChar { ch: '@', span: Span::new(6, 7) }, error);
+    }
+    #[test]
+    fn mismatched_parenthesis_span() {
+        let error = Validate::validate_input("(1 + 2)) * 3").unwrap_err();
+        assert_eq!(RpnError::MismatchedParenthesis { span: Span::new(7, 8) }, error);
+    }
+    #[test]
+    fn unbound_variables_span() {
+        let error = crate::solve_with_variables("1 + x * yy", &HashMap::new()).unwrap_err();
+        assert_eq!(Span::new(4, 5), error.span());
+        assert_eq!(
+            RpnError::UnboundVariables { variables: vec![
+                ("x".to_string(), Span::new(4, 5)),
+                ("yy".to_string(), Span::new(8, 10)),
+            ]},
+            error
+        );
+    }
+}
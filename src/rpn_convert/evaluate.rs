@@ -0,0 +1,456 @@
+use super::*;
+
+/// The result of solving an expression, a number or, from comparisons and
+/// boolean operators, true or false
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+/// The type of a value, to report which one an operator or function needed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Number,
+    Bool,
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Number(_) => ValueType::Number,
+            Value::Bool(_) => ValueType::Bool,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(num) => Some(*num),
+            Value::Bool(_) => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            Value::Number(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(num) => write!(f, "{}", num),
+            Value::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Number => write!(f, "a number"),
+            ValueType::Bool => write!(f, "true or false"),
+        }
+    }
+}
+
+/// Evaluates an rpn stack, substituting every variable and constant with
+/// its value from the context. Fails with `RpnError::UnboundVariables` listing
+/// every variable that has no value before any calculation is attempted.
+/// Functions defined in the context are solved from their body each time they are called.
+/// An expression giving true or false, such as a comparison, fails with `RpnError::TypeMismatch`.
+pub fn evaluate(rpn: &Stack, context: &Context) -> Result<f64, RpnError> {
+    let (value, span) = evaluate_call(rpn, context, &[], 0)?;
+    value.as_number().ok_or(RpnError::TypeMismatch { expected: ValueType::Number, span })
+}
+
+/// Evaluates an rpn stack as `evaluate` does, giving either a number or,
+/// for comparisons and boolean operators, true or false
+pub fn evaluate_value(rpn: &Stack, context: &Context) -> Result<Value, RpnError> {
+    evaluate_call(rpn, context, &[], 0).map(|(value, _)| value)
+}
+
+/// Evaluates the stack with the arguments of the defined function it is the body of,
+/// `depth` calls down from the expression being solved
+fn evaluate_call(rpn: &Stack, context: &Context, args: &[(String, f64)], depth: usize) -> Result<(Value, Span), RpnError> {
+    check_bound(rpn, context, args)?;
+
+    let operators = context.operators();
+    // Each value keeps the span it was calculated from
+    let mut total_stack: Vec<(Value, Span)> = Vec::new();
+    let tokens = rpn.tokens();
+    let mut position = 0;
+    while let Some(token) = tokens.get(position) {
+        position += 1;
+        match &token.value {
+            MathValue::Num(num) => total_stack.push((Value::Number(*num), token.span)),
+            MathValue::Bool(value) => total_stack.push((Value::Bool(*value), token.span)),
+            MathValue::Alge(al) | MathValue::Const(al) => {
+                // Every name is bound, checked above
+                let value = resolve(al, context, args).unwrap_or(f64::NAN);
+                total_stack.push((Value::Number(value), token.span));
+            },
+            MathValue::Op(op) => {
+                let arity = operators.operator(op).map_or(2, |operator| operator.arity);
+                let (operands, span) = pop_operands(&mut total_stack, arity, op, token.span)?;
+                let answer = apply(op, &operands, context)?
+                    .ok_or(RpnError::InvalidOperator { op: op.to_string(), span: token.span })?;
+                total_stack.push((answer, span));
+            },
+            // Only the branch taken is evaluated
+            MathValue::JumpUnless(skip) => {
+                let (condition, _) = pop_operands(&mut total_stack, 1, "?", token.span)?;
+                if !bools(&condition)?[0] {
+                    position = position.saturating_add(*skip);
+                }
+            },
+            MathValue::Jump(skip) => position = position.saturating_add(*skip),
+            // A conditional written as a call, as in rpn written by hand, has both branches evaluated already
+            MathValue::Func(name, 3) if name == functions::CONDITIONAL => {
+                let (operands, span) = pop_operands(&mut total_stack, 3, name, token.span)?;
+                let taken = if bools(&operands[..1])?[0] { operands[1].0 } else { operands[2].0 };
+                total_stack.push((taken, span));
+            },
+            MathValue::Func(name, arity) => {
+                // Built-in functions can't be redefined
+                let builtin = functions::builtin(name);
+                let defined = context.function(name).filter(|_| builtin.is_none());
+                let expected = builtin.map(|function| function.arity)
+                    .or(defined.map(|function| function.params().len()))
+                    .ok_or_else(|| RpnError::UnknownFunction { name: name.to_string(), span: token.span })?;
+                if expected != *arity {
+                    return Err(RpnError::ArityMismatch {
+                        name: name.to_string(), expected, found: *arity, span: token.span
+                    });
+                }
+                let (operands, span) = pop_operands(&mut total_stack, *arity, name, token.span)?;
+                let values = numbers(&operands)?;
+                let answer = match defined {
+                    Some(function) => {
+                        if depth >= context.recursion_limit() {
+                            return Err(RpnError::RecursionLimit { name: name.to_string(), span: token.span });
+                        }
+                        let params = function.params().iter().cloned().zip(values).collect::<Vec<(String, f64)>>();
+                        evaluate_call(function.body(), context, &params, depth + 1)?.0
+                    },
+                    None => Value::Number(builtin.map_or(f64::NAN, |function| function.call(&values))),
+                };
+                total_stack.push((answer, span));
+            },
+        }
+    }
+    match total_stack.len() {
+        0 => Err(RpnError::TooShort { span: Span::default() }),
+        1 => Ok(total_stack[0]),
+        _ => {
+            let span = total_stack.iter().map(|(_, span)| *span).reduce(Span::join).unwrap_or_default();
+            Err(RpnError::LeftoverOperands { span })
+        },
+    }
+}
+
+/// Applies an operator to its operands. Built-in comparisons give true or false and
+/// boolean operators take them, any other operator takes numbers.
+/// None if the operator is unknown or given the wrong number of operands
+fn apply(op: &str, operands: &[(Value, Span)], context: &Context) -> Result<Option<Value>, RpnError> {
+    let operators = context.operators();
+    let answer = match (op, operands) {
+        _ if operators.is_custom(op) => operators.calculate(op, &numbers(operands)?, context.modulo_mode()).map(Value::Number),
+        (AND | OR | NOT, _) => match (op, &bools(operands)?[..]) {
+            (AND, [a, b]) => Some(*a && *b),
+            (OR, [a, b]) => Some(*a || *b),
+            (NOT, [a]) => Some(!*a),
+            _ => None,
+        }.map(Value::Bool),
+        // Booleans can be compared for equality, but are not ordered
+        (EQUAL | NOT_EQUAL, [(Value::Bool(_), _), _]) => match &bools(operands)?[..] {
+            [a, b] => Some(Value::Bool((a == b) == (op == EQUAL))),
+            _ => None,
+        },
+        ("<" | ">" | LESS_EQUAL | GREATER_EQUAL | EQUAL | NOT_EQUAL, _) => match numbers(operands)?[..] {
+            [a, b] => compare(a, b, op).map(Value::Bool),
+            _ => None,
+        },
+        _ => operators.calculate(op, &numbers(operands)?, context.modulo_mode()).map(Value::Number),
+    };
+    Ok(answer)
+}
+
+/// The operands as numbers, or a type mismatch at the first that is true or false
+fn numbers(operands: &[(Value, Span)]) -> Result<Vec<f64>, RpnError> {
+    operands.iter()
+        .map(|(value, span)| value.as_number().ok_or(RpnError::TypeMismatch { expected: ValueType::Number, span: *span }))
+        .collect()
+}
+
+/// The operands as booleans, or a type mismatch at the first that is a number
+fn bools(operands: &[(Value, Span)]) -> Result<Vec<bool>, RpnError> {
+    operands.iter()
+        .map(|(value, span)| value.as_bool().ok_or(RpnError::TypeMismatch { expected: ValueType::Bool, span: *span }))
+        .collect()
+}
+
+/// Result of an operator on numbers, if it is finite and the same in every context.
+/// % and // of negatives depend on the modulo mode.
+pub(crate) fn calculate_constant(op: &str, args: &[f64]) -> Option<f64> {
+    let answer = match args {
+        [a] => calculate_unary(*a, op)?,
+        [a, b] if (op == "%" || op == INT_DIV) && (*a < 0.0 || *b < 0.0) => return None,
+        [a, b] => calculate(*a, *b, op, ModuloMode::Truncated)?,
+        _ => return None,
+    };
+    answer.is_finite().then_some(answer)
+}
+
+/// Pops the operands of an operator or function, returning them in the
+/// order they were written with the span covering them and the operator
+fn pop_operands(total_stack: &mut Vec<(Value, Span)>, count: usize, op: &str, span: Span) -> Result<(Vec<(Value, Span)>, Span), RpnError> {
+    if total_stack.len() < count {
+        return Err(RpnError::StackUnderflow { op: op.to_string(), span });
+    }
+    let operands = total_stack.split_off(total_stack.len() - count);
+    let span = operands.iter().fold(span, |joined, (_, span)| joined.join(*span));
+    Ok((operands, span))
+}
+
+/// Calculates the solution from the given operators
+/// Works left to right - a op b. None if the operator is unknown
+pub(crate) fn calculate(a: f64, b: f64, op: &str, modulo_mode: ModuloMode) -> Option<f64> {
+    match op {
+        "+" => Some(a + b),
+        "-" => Some(a - b),
+        "*" => Some(a * b),
+        "/" => Some(a / b),
+        "^" => Some(a.powf(b)),
+        "%" => Some(modulo(a, b, modulo_mode)),
+        INT_DIV => match modulo_mode {
+            ModuloMode::Truncated => Some((a / b).trunc()),
+            ModuloMode::Floored => Some((a / b).floor()),
+        },
+        _ => None,
+    }
+}
+
+/// Compares a with b. None if the operator is not a comparison
+pub(crate) fn compare(a: f64, b: f64, op: &str) -> Option<bool> {
+    match op {
+        "<" => Some(a < b),
+        ">" => Some(a > b),
+        LESS_EQUAL => Some(a <= b),
+        GREATER_EQUAL => Some(a >= b),
+        EQUAL => Some(a == b),
+        NOT_EQUAL => Some(a != b),
+        _ => None,
+    }
+}
+
+fn modulo(a: f64, b: f64, modulo_mode: ModuloMode) -> f64 {
+    let remainder = a % b;
+    match modulo_mode {
+        // Move a remainder with the wrong sign over to the divisor's side
+        ModuloMode::Floored if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) => remainder + b,
+        _ => remainder,
+    }
+}
+
+/// Calculates the solution of a prefix operator - op a.
+/// None if the operator is unknown
+pub(crate) fn calculate_unary(a: f64, op: &str) -> Option<f64> {
+    match op {
+        NEGATE => Some(-a),
+        _ => None,
+    }
+}
+
+/// Fails with `RpnError::UnboundVariables` if any variable in the stack has no value
+pub(crate) fn check_bound(rpn: &Stack, context: &Context, args: &[(String, f64)]) -> Result<(), RpnError> {
+    let unbound = unbound_variables(rpn, context, args);
+    match unbound.is_empty() {
+        true => Ok(()),
+        false => Err(RpnError::UnboundVariables { variables: unbound }),
+    }
+}
+
+/// Lists every variable in the stack without a value, in order of first use
+fn unbound_variables(rpn: &Stack, context: &Context, args: &[(String, f64)]) -> Vec<(String, Span)> {
+    let mut unbound: Vec<(String, Span)> = Vec::new();
+    for token in rpn.tokens() {
+        if let MathValue::Alge(al) | MathValue::Const(al) = &token.value {
+            let name = al.strip_prefix('-').unwrap_or(al);
+            if resolve(al, context, args).is_none() && !unbound.iter().any(|(n, _)| n == name) {
+                unbound.push((name.to_string(), token.span));
+            }
+        }
+    }
+    unbound
+}
+
+/// Resolves an algebraic token to a value, from the arguments of the function
+/// being called before the context. A leading '-' (from ' ! ' or ' ¬ ') negates the variable.
+fn resolve(token: &str, context: &Context, args: &[(String, f64)]) -> Option<f64> {
+    let value_of = |name: &str| {
+        args.iter().find(|(param, _)| param == name).map(|(_, value)| *value).or_else(|| context.value_of(name))
+    };
+    match token.strip_prefix('-') {
+        Some(name) => value_of(name).map(|value| -value),
+        None => value_of(token),
+    }
+}
+
+#[cfg(test)]
+mod evaluate_tests {
+    use super::*;
+
+    fn solve(input: &str, context: &Context) -> Result<f64, RpnError> {
+        evaluate(&shunting_yard::convert_in_to_post_fix(input)?, context)
+    }
+
+    fn solve_value(input: &str, context: &Context) -> Result<Value, RpnError> {
+        evaluate_value(&shunting_yard::convert_in_to_post_fix(input)?, context)
+    }
+
+    fn variables(pairs: &[(&str, f64)]) -> Context {
+        let mut context = Context::new();
+        for (name, value) in pairs {
+            context.set_variable(name, *value);
+        }
+        context
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let rpn = shunting_yard::convert_in_to_post_fix("x * (1 + rate)").unwrap();
+        assert_eq!(3.0, evaluate(&rpn, &variables(&[("x", 2.0), ("rate", 0.5)])).unwrap());
+    }
+    #[test]
+    fn negated_variable() {
+        let rpn = shunting_yard::convert_in_to_post_fix("10 + !x").unwrap();
+        assert_eq!(6.0, evaluate(&rpn, &variables(&[("x", 4.0)])).unwrap());
+    }
+    #[test]
+    fn functions() {
+        let rpn = shunting_yard::convert_in_to_post_fix("max(x, 2) * sqrt(16) + log(2, 8)").unwrap();
+        assert_eq!(23.0, evaluate(&rpn, &variables(&[("x", 5.0)])).unwrap());
+    }
+    #[test]
+    fn constants() {
+        let rpn = shunting_yard::convert_in_to_post_fix("2*pi*3 - tau*3 + !e + g").unwrap();
+        let mut context = Context::new();
+        context.set_constant("g", 9.81);
+        assert_eq!(9.81 - std::f64::consts::E, evaluate(&rpn, &context).unwrap());
+    }
+    #[test]
+    fn modulo_modes() {
+        let rpn = shunting_yard::convert_in_to_post_fix("x % 3 + (x // 3) * 10").unwrap();
+        let mut context = variables(&[("x", -7.0)]);
+        assert_eq!(-21.0, evaluate(&rpn, &context).unwrap());
+        context.set_modulo_mode(ModuloMode::Floored);
+        assert_eq!(-28.0, evaluate(&rpn, &context).unwrap());
+        context.set_variable("x", 7.0);
+        assert_eq!(21.0, evaluate(&rpn, &context).unwrap());
+    }
+    #[test]
+    fn names_are_never_numbers() {
+        let rpn = shunting_yard::convert_in_to_post_fix("inf + nan").unwrap();
+        let error = evaluate(&rpn, &Context::new()).unwrap_err();
+        assert_eq!("Unbound variables: inf, nan", error.to_string());
+    }
+    #[test]
+    fn function_arity() {
+        let rpn = shunting_yard::convert_in_to_post_fix("1 + max(1, 2, 3)").unwrap();
+        let error = evaluate(&rpn, &Context::new()).unwrap_err();
+        let expected = RpnError::ArityMismatch { name: "max".to_string(), expected: 2, found: 3, span: Span::new(4, 7) };
+        assert_eq!(expected, error);
+    }
+    #[test]
+    fn unknown_function() {
+        let rpn = shunting_yard::convert_in_to_post_fix("foo(1)").unwrap();
+        let error = evaluate(&rpn, &Context::new()).unwrap_err();
+        assert_eq!(RpnError::UnknownFunction { name: "foo".to_string(), span: Span::new(0, 3) }, error);
+    }
+    #[test]
+    fn stack_underflow() {
+        let rpn = shunting_yard::convert_in_to_post_fix("3 + * 4").unwrap();
+        let error = evaluate(&rpn, &Context::new()).unwrap_err();
+        assert_eq!(RpnError::StackUnderflow { op: "+".to_string(), span: Span::new(2, 3) }, error);
+    }
+    #[test]
+    fn lists_every_unbound_variable() {
+        let rpn = shunting_yard::convert_in_to_post_fix("a * x + b - !a").unwrap();
+        let error = evaluate(&rpn, &variables(&[("x", 1.0)])).unwrap_err();
+        match error {
+            RpnError::UnboundVariables { variables } => {
+                let names: Vec<&str> = variables.iter().map(|(name, _)| name.as_str()).collect();
+                assert_eq!(vec!["a", "b"], names);
+            },
+            _ => panic!("Expected unbound variables, got {:?}", error),
+        }
+    }
+    #[test]
+    fn defined_functions() {
+        let mut context = variables(&[("y", 10.0)]);
+        context.define("f(x, y) = x^2 + y").unwrap()
+            .define("g(x) = f(x, 1) * -x + y").unwrap()
+            .define("half(x) = x / 2").unwrap();
+        assert_eq!(Ok(13.0), solve("f(3, 4)", &context));
+        // Parameters shadow variables, others come from the context
+        let mut with_x = context.clone();
+        with_x.set_variable("x", 20.0);
+        assert_eq!(Ok(-10.0), solve("g(2) + half(!x)", &with_x));
+        assert_eq!(Ok(2.0), solve("half(f(1, 3))", &context));
+        let error = solve("1 + f(2)", &context).unwrap_err();
+        assert_eq!(RpnError::ArityMismatch { name: "f".to_string(), expected: 2, found: 1, span: Span::new(4, 5) }, error);
+    }
+    #[test]
+    fn recursion_limit() {
+        let mut context = Context::new();
+        context.define("loop(x) = loop(x + 1)").unwrap().define("deep(x) = half(half(x))").unwrap().define("half(x) = x / 2").unwrap();
+        assert_eq!(Err(RpnError::RecursionLimit { name: "loop".to_string(), span: Span::new(10, 14) }), solve("loop(1)", &context));
+        assert_eq!(Ok(1.0), solve("deep(4)", &context));
+        context.set_recursion_limit(1);
+        assert_eq!(Err(RpnError::RecursionLimit { name: "half".to_string(), span: Span::new(15, 19) }), solve("deep(4)", &context));
+    }
+    #[test]
+    fn unbound_in_body() {
+        let mut context = Context::new();
+        context.define("f(x) = x * rate").unwrap();
+        let error = solve("f(2)", &context).unwrap_err();
+        assert_eq!(RpnError::UnboundVariables { variables: vec![("rate".to_string(), Span::new(11, 15))] }, error);
+    }
+    #[test]
+    fn comparisons() {
+        let mut context = variables(&[("pressure", 80.0), ("temp", -2.0)]);
+        context.define("valid(p) = p > 0 and p < 100").unwrap();
+        assert_eq!(Ok(Value::Bool(false)), solve_value("pressure < 100 and temp >= 0", &context));
+        assert_eq!(Ok(Value::Bool(true)), solve_value("pressure <= 80 and not temp >= 0", &context));
+        assert_eq!(Ok(Value::Bool(true)), solve_value("1 + 1 == 2 != false", &context));
+        assert_eq!(Ok(Value::Bool(true)), solve_value("valid(pressure) or valid(temp)", &context));
+        assert_eq!(Ok(Value::Number(2.0)), solve_value("max(1, 2)", &context));
+    }
+    #[test]
+    fn type_mismatch() {
+        let context = Context::new();
+        let mismatch = |expected, start, end| RpnError::TypeMismatch { expected, span: Span::new(start, end) };
+        assert_eq!(Err(mismatch(ValueType::Bool, 0, 1)), solve_value("1 and true", &context));
+        assert_eq!(Err(mismatch(ValueType::Number, 5, 10)), solve("2 + (1 < 2)", &context));
+        assert_eq!(Err(mismatch(ValueType::Number, 0, 4)), solve_value("true < false", &context));
+        assert_eq!(Err(mismatch(ValueType::Bool, 8, 9)), solve_value("true == 1", &context));
+        assert_eq!(Err(mismatch(ValueType::Number, 0, 5)), solve("1 < 2", &context));
+        assert_eq!(Err(mismatch(ValueType::Number, 5, 10)), solve("sqrt(x > 0)", &variables(&[("x", 1.0)])));
+    }
+    #[test]
+    fn conditionals() {
+        let mut context = variables(&[("x", -4.0)]);
+        context.define("fact(n) = n <= 1 ? 1 : n * fact(n - 1)").unwrap();
+        assert_eq!(Ok(4.0), solve("x > 0 ? x : -x", &context));
+        assert_eq!(Ok(-8.0), solve("if(x < 0, x * 2, sqrt(x))", &context));
+        assert_eq!(Ok(120.0), solve("fact(5)", &context));
+        // The branch not taken is never evaluated, so can't fail
+        assert_eq!(Ok(1.0), solve("x != 0 ? 1 : (1 and 2)", &context));
+        assert_eq!(Ok(3.0), evaluate(&postfix::parse("true 3 4 if").unwrap(), &context));
+        let error = RpnError::TypeMismatch { expected: ValueType::Bool, span: Span::new(0, 1) };
+        assert_eq!(Err(error), solve("x ? 1 : 2", &context));
+    }
+}
@@ -0,0 +1,335 @@
+use super::*;
+
+/// An expression tree, as returned by `ast_tree::parse`.
+/// Operators are the symbols used in rpn, so negation is `~`
+/// and `**` is `^`.
+/// Walks over the tree keep their own stack, so a long chain such as a 10k term sum
+/// can't overflow the call stack.
+#[derive(Debug)]
+pub enum Expr {
+    Number(f64),
+    /// `true` or `false`
+    Bool(bool),
+    /// A variable, or a constant such as pi
+    Variable(String),
+    Binary { op: String, left: Box<Expr>, right: Box<Expr> },
+    Unary { op: String, operand: Box<Expr> },
+    /// A function call with its arguments in the order they were written.
+    /// Conditionals, `c ? a : b`, are calls to `if`
+    Call { name: String, args: Vec<Expr> },
+}
+
+/// Builds a value bottom up from an expression, each method receiving the
+/// already folded operands. Used through `Expr::fold`.
+pub trait Fold {
+    type Output;
+
+    fn number(&mut self, value: f64) -> Self::Output;
+    fn boolean(&mut self, value: bool) -> Self::Output;
+    fn variable(&mut self, name: &str) -> Self::Output;
+    fn binary(&mut self, op: &str, left: Self::Output, right: Self::Output) -> Self::Output;
+    fn unary(&mut self, op: &str, operand: Self::Output) -> Self::Output;
+    fn call(&mut self, name: &str, args: Vec<Self::Output>) -> Self::Output;
+}
+
+impl Expr {
+    pub fn variable(name: &str) -> Expr {
+        Expr::Variable(name.to_string())
+    }
+
+    pub fn binary(op: &str, left: Expr, right: Expr) -> Expr {
+        Expr::Binary { op: op.to_string(), left: Box::new(left), right: Box::new(right) }
+    }
+
+    pub fn unary(op: &str, operand: Expr) -> Expr {
+        Expr::Unary { op: op.to_string(), operand: Box::new(operand) }
+    }
+
+    pub fn call(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call { name: name.to_string(), args }
+    }
+
+    /// True if the variable appears anywhere in the expression
+    pub fn depends_on(&self, variable: &str) -> bool {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expr::Variable(name) if name == variable => return true,
+                Expr::Binary { left, right, .. } => pending.extend([left.as_ref(), right.as_ref()]),
+                Expr::Unary { operand, .. } => pending.push(operand),
+                Expr::Call { args, .. } => pending.extend(args),
+                _ => (),
+            }
+        }
+        false
+    }
+
+    /// Folds the tree from its leaves up, the operands of each
+    /// part in the order they were written
+    pub fn fold<F: Fold>(&self, folder: &mut F) -> F::Output {
+        // Each part is visited on the way down, then folded on the way back up
+        // once its operands are on top of `folded`
+        let mut visits: Vec<(&Expr, bool)> = vec![(self, false)];
+        let mut folded: Vec<F::Output> = Vec::new();
+        while let Some((expr, operands_folded)) = visits.pop() {
+            if !operands_folded {
+                visits.push((expr, true));
+                // The last operand is pushed first so the first is folded first
+                match expr {
+                    Expr::Binary { left, right, .. } => visits.extend([(right.as_ref(), false), (left.as_ref(), false)]),
+                    Expr::Unary { operand, .. } => visits.push((operand, false)),
+                    Expr::Call { args, .. } => visits.extend(args.iter().rev().map(|arg| (arg, false))),
+                    _ => (),
+                }
+                continue;
+            }
+            let output = match expr {
+                Expr::Number(value) => folder.number(*value),
+                Expr::Bool(value) => folder.boolean(*value),
+                Expr::Variable(name) => folder.variable(name),
+                Expr::Binary { op, .. } => {
+                    let mut operands = folded.split_off(folded.len() - 2).into_iter();
+                    // Both operands were folded just before
+                    let (Some(left), Some(right)) = (operands.next(), operands.next()) else { unreachable!() };
+                    folder.binary(op, left, right)
+                },
+                Expr::Unary { op, .. } => {
+                    let Some(operand) = folded.pop() else { unreachable!() };
+                    folder.unary(op, operand)
+                },
+                Expr::Call { name, args } => {
+                    let args = folded.split_off(folded.len() - args.len());
+                    folder.call(name, args)
+                },
+            };
+            folded.push(output);
+        }
+        // The whole tree is the only part left
+        let Some(output) = folded.pop() else { unreachable!() };
+        output
+    }
+
+    /// Solves the expression, taking variables and constants from the context
+    pub fn evaluate(&self, context: &Context) -> Result<f64, RpnError> {
+        evaluate::evaluate(&self.to_stack(), context)
+    }
+
+    /// Solves the expression as `evaluate` does, where it may also be a comparison
+    pub fn evaluate_value(&self, context: &Context) -> Result<Value, RpnError> {
+        evaluate::evaluate_value(&self.to_stack(), context)
+    }
+
+    /// The expression in rpn order. The tokens have no position in any input.
+    pub fn to_stack(&self) -> Stack {
+        let mut stack = Stack::new();
+        self.fold(&mut ToStack(&mut stack));
+        stack
+    }
+}
+
+/// Copies the tree part by part, so a long chain can't overflow the stack
+impl Clone for Expr {
+    fn clone(&self) -> Expr {
+        self.fold(&mut Rebuild)
+    }
+}
+
+/// Compares the trees part by part, so a long chain can't overflow the stack
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let same = match pair {
+                (Expr::Number(a), Expr::Number(b)) => a == b,
+                (Expr::Bool(a), Expr::Bool(b)) => a == b,
+                (Expr::Variable(a), Expr::Variable(b)) => a == b,
+                (Expr::Binary { op, left, right }, Expr::Binary { op: other_op, left: other_left, right: other_right }) => {
+                    pending.extend([(left.as_ref(), other_left.as_ref()), (right.as_ref(), other_right.as_ref())]);
+                    op == other_op
+                },
+                (Expr::Unary { op, operand }, Expr::Unary { op: other_op, operand: other_operand }) => {
+                    pending.push((operand, other_operand));
+                    op == other_op
+                },
+                (Expr::Call { name, args }, Expr::Call { name: other_name, args: other_args }) => {
+                    pending.extend(args.iter().zip(other_args));
+                    name == other_name && args.len() == other_args.len()
+                },
+                _ => false,
+            };
+            if !same {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Builds the same tree again
+struct Rebuild;
+impl Fold for Rebuild {
+    type Output = Expr;
+
+    fn number(&mut self, value: f64) -> Expr {
+        Expr::Number(value)
+    }
+    fn boolean(&mut self, value: bool) -> Expr {
+        Expr::Bool(value)
+    }
+    fn variable(&mut self, name: &str) -> Expr {
+        Expr::variable(name)
+    }
+    fn binary(&mut self, op: &str, left: Expr, right: Expr) -> Expr {
+        Expr::binary(op, left, right)
+    }
+    fn unary(&mut self, op: &str, operand: Expr) -> Expr {
+        Expr::unary(op, operand)
+    }
+    fn call(&mut self, name: &str, args: Vec<Expr>) -> Expr {
+        Expr::call(name, args)
+    }
+}
+
+/// Prints the expression as infix, with as few brackets as the built-in operators need.
+/// Any other operator has its operands bracketed, see `infix::convert_expr_to_in_fix_with`.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", infix::convert_expr_to_in_fix_with(self, &operators::default_operators))
+    }
+}
+
+/// Pushes each part of the expression onto the stack after its operands,
+/// counting the tokens pushed for each part
+struct ToStack<'a>(&'a mut Stack);
+impl Fold for ToStack<'_> {
+    type Output = usize;
+
+    fn number(&mut self, value: f64) -> usize {
+        self.0.push(Token::new(MathValue::Num(value), Span::default()));
+        1
+    }
+    fn boolean(&mut self, value: bool) -> usize {
+        self.0.push(Token::new(MathValue::Bool(value), Span::default()));
+        1
+    }
+    fn variable(&mut self, name: &str) -> usize {
+        let value = match context::builtin_constant(name) {
+            Some(_) => MathValue::Const(name.to_string()),
+            None => MathValue::Alge(name.to_string()),
+        };
+        self.0.push(Token::new(value, Span::default()));
+        1
+    }
+    fn binary(&mut self, op: &str, left: usize, right: usize) -> usize {
+        self.0.push(Token::new(MathValue::Op(op.to_string()), Span::default()));
+        left + right + 1
+    }
+    fn unary(&mut self, op: &str, operand: usize) -> usize {
+        self.0.push(Token::new(MathValue::Op(op.to_string()), Span::default()));
+        operand + 1
+    }
+    fn call(&mut self, name: &str, args: Vec<usize>) -> usize {
+        // The arguments of a conditional are already pushed, so the jumps go between them
+        if let (functions::CONDITIONAL, &[condition, then, otherwise]) = (name, &args[..]) {
+            let mut branches = self.0.elements.split_off(self.0.len() - then - otherwise);
+            let otherwise = branches.split_off(then);
+            let condition = self.0.elements.split_off(self.0.len() - condition);
+            let rpn = conditional(condition, branches, otherwise, (Span::default(), Span::default()));
+            let length = rpn.len();
+            self.0.elements.extend(rpn);
+            return length;
+        }
+        self.0.push(Token::new(MathValue::Func(name.to_string(), args.len()), Span::default()));
+        args.into_iter().sum::<usize>() + 1
+    }
+}
+
+#[cfg(test)]
+mod expr_tests {
+    use super::*;
+
+    /// Every variable, in order of first use
+    struct Variables(Vec<String>);
+    impl Fold for Variables {
+        type Output = ();
+        fn number(&mut self, _: f64) {}
+        fn boolean(&mut self, _: bool) {}
+        fn variable(&mut self, name: &str) {
+            if !self.0.iter().any(|seen| seen == name) {
+                self.0.push(name.to_string());
+            }
+        }
+        fn binary(&mut self, _: &str, _: (), _: ()) {}
+        fn unary(&mut self, _: &str, _: ()) {}
+        fn call(&mut self, _: &str, _: Vec<()>) {}
+    }
+
+    /// Levels of nesting below the root
+    struct Depth;
+    impl Fold for Depth {
+        type Output = usize;
+        fn number(&mut self, _: f64) -> usize { 0 }
+        fn boolean(&mut self, _: bool) -> usize { 0 }
+        fn variable(&mut self, _: &str) -> usize { 0 }
+        fn binary(&mut self, _: &str, left: usize, right: usize) -> usize { left.max(right) + 1 }
+        fn unary(&mut self, _: &str, operand: usize) -> usize { operand + 1 }
+        fn call(&mut self, _: &str, args: Vec<usize>) -> usize { args.into_iter().max().unwrap_or(0) + 1 }
+    }
+
+    /// How often each operator is used
+    struct OperatorCount(HashMap<String, usize>);
+    impl Fold for OperatorCount {
+        type Output = ();
+        fn number(&mut self, _: f64) {}
+        fn boolean(&mut self, _: bool) {}
+        fn variable(&mut self, _: &str) {}
+        fn binary(&mut self, op: &str, _: (), _: ()) {
+            *self.0.entry(op.to_string()).or_insert(0) += 1;
+        }
+        fn unary(&mut self, op: &str, _: ()) {
+            *self.0.entry(op.to_string()).or_insert(0) += 1;
+        }
+        fn call(&mut self, _: &str, _: Vec<()>) {}
+    }
+
+    #[test]
+    fn folds() {
+        let expr = ast_tree::parse("x * (y + 2) - max(x, -z ^ 2) * x").unwrap();
+        let mut variables = Variables(Vec::new());
+        expr.fold(&mut variables);
+        assert_eq!(vec!["x", "y", "z"], variables.0);
+        assert_eq!(5, expr.fold(&mut Depth));
+        let mut count = OperatorCount(HashMap::new());
+        expr.fold(&mut count);
+        let expected = [("*", 2), ("+", 1), ("-", 1), ("^", 1), (NEGATE, 1)].map(|(op, count)| (op.to_string(), count));
+        assert_eq!(HashMap::from(expected), count.0);
+    }
+    #[test]
+    fn booleans_are_not_variables() {
+        let expr = ast_tree::parse("x < 1 == true or not false").unwrap();
+        let mut variables = Variables(Vec::new());
+        expr.fold(&mut variables);
+        assert_eq!(vec!["x"], variables.0);
+        assert!(!expr.depends_on("true"));
+        assert_eq!("x 1 < true == false not or", expr.to_stack().as_string());
+    }
+    #[test]
+    fn prints_rpn_and_infix() {
+        let expr = ast_tree::parse("(!2 + x) * -sin(pi // 2)").unwrap();
+        assert_eq!("-2 x + pi 2 // sin ~ *", expr.to_stack().as_string());
+        assert_eq!("(¬2 + x) * -sin(pi // 2)", expr.to_string());
+    }
+    #[test]
+    fn prints_any_operator() {
+        let mut operators = OperatorTable::new();
+        operators.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1])).unwrap();
+        let mut options = ParseOptions::new();
+        options.set_operators(operators.clone());
+        let expr = ast_tree::parse_with("(a | b) * 2 + c | d", &options).unwrap();
+        assert_eq!("(a | b) * 2 + (c | d)", expr.to_string());
+        assert_eq!("a | b * 2 + c | d", infix::convert_expr_to_in_fix_with(&expr, &operators));
+        let expr = Expr::binary("$", Expr::unary("+", Expr::binary("-", Expr::variable("x"), Expr::Number(-1.0))), Expr::variable("y"));
+        assert_eq!("(+(x - ¬1)) $ y", expr.to_string());
+        assert_eq!("if(x, y) * 2", Expr::binary("*", Expr::call(functions::CONDITIONAL, vec![Expr::variable("x"), Expr::variable("y")]), Expr::Number(2.0)).to_string());
+    }
+}
@@ -0,0 +1,69 @@
+use super::*;
+
+/// A built-in function taking a fixed number of arguments
+#[derive(Debug, Clone, Copy)]
+pub struct Function {
+    pub arity: usize,
+    apply: fn(&[f64]) -> f64,
+}
+impl Function {
+    const fn new(arity: usize, apply: fn(&[f64]) -> f64) -> Function {
+        Function { arity, apply }
+    }
+
+    /// Applies the function to exactly `arity` arguments, in call order
+    pub fn call(&self, args: &[f64]) -> f64 {
+        (self.apply)(args)
+    }
+}
+
+lazy_static! {
+    static ref builtin_functions: HashMap<&'static str, Function> = HashMap::from([
+        ("sin", Function::new(1, |args| args[0].sin())),
+        ("cos", Function::new(1, |args| args[0].cos())),
+        ("tan", Function::new(1, |args| args[0].tan())),
+        ("asin", Function::new(1, |args| args[0].asin())),
+        ("acos", Function::new(1, |args| args[0].acos())),
+        ("atan", Function::new(1, |args| args[0].atan())),
+        ("atan2", Function::new(2, |args| args[0].atan2(args[1]))),
+        ("sqrt", Function::new(1, |args| args[0].sqrt())),
+        ("abs", Function::new(1, |args| args[0].abs())),
+        ("ln", Function::new(1, |args| args[0].ln())),
+        ("log10", Function::new(1, |args| args[0].log10())),
+        ("log", Function::new(2, |args| args[1].log(args[0]))),
+        ("exp", Function::new(1, |args| args[0].exp())),
+        ("floor", Function::new(1, |args| args[0].floor())),
+        ("ceil", Function::new(1, |args| args[0].ceil())),
+        ("round", Function::new(1, |args| args[0].round())),
+        ("min", Function::new(2, |args| args[0].min(args[1]))),
+        ("max", Function::new(2, |args| args[0].max(args[1]))),
+    ]);
+}
+
+/// Name of the conditional `if(c, a, b)`. It is not a `Function`, as only
+/// the branch it takes is evaluated, so it is written with jumps in rpn.
+pub const CONDITIONAL: &str = "if";
+
+/// Looks up a built-in function by name
+pub fn builtin(name: &str) -> Option<Function> {
+    builtin_functions.get(name).copied()
+}
+
+/// Names of every built-in function, sorted
+pub fn builtin_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = builtin_functions.keys().copied().collect();
+    names.sort_unstable();
+    names
+}
+
+#[cfg(test)]
+mod functions_tests {
+    use super::*;
+
+    #[test]
+    fn arguments_in_call_order() {
+        assert_eq!(3.0, builtin("log").unwrap().call(&[2.0, 8.0]));
+        assert_eq!(0.0, builtin("atan2").unwrap().call(&[0.0, 1.0]));
+        assert_eq!(2.0, builtin("min").unwrap().call(&[2.0, 5.0]));
+    }
+}
@@ -0,0 +1,276 @@
+use super::*;
+
+/// A rebuilt piece of infix with the operator applied last, None for single values
+struct Fragment {
+    text: String,
+    operator: Option<Operator>,
+}
+impl Fragment {
+    fn value(text: String) -> Fragment {
+        Fragment { text, operator: None }
+    }
+
+    fn is_prefix(&self) -> bool {
+        self.operator.is_some_and(|operator| operator.arity == 1)
+    }
+
+    fn precedence(&self) -> Option<u8> {
+        self.operator.map(|operator| operator.precedence)
+    }
+
+    /// The text, bracketed if needed
+    fn wrapped(self, needs_brackets: bool) -> String {
+        match needs_brackets {
+            true => format!("({})", self.text),
+            false => self.text,
+        }
+    }
+}
+
+/// Converts an rpn stack back to infix, adding only the brackets needed to keep
+/// the order given by the precedence and associativity of the built-in operators.
+/// Negative values are written with ' ¬ ' so they stay single values.
+pub fn convert_post_to_in_fix(rpn: &Stack) -> Result<String, RpnError> {
+    convert_post_to_in_fix_with(rpn, &operators::default_operators)
+}
+
+/// Converts an rpn stack back to infix, bracketing by the operators in the table
+pub fn convert_post_to_in_fix_with(rpn: &Stack, operators: &OperatorTable) -> Result<String, RpnError> {
+    convert_tokens(rpn.tokens(), operators, 0).map(|(fragment, _)| fragment.text)
+}
+
+/// Rebuilds the tokens as a single piece of infix, with the span it covers.
+/// `depth` counts the conditionals the tokens are a branch of.
+fn convert_tokens(tokens: &[Token], operators: &OperatorTable, depth: usize) -> Result<(Fragment, Span), RpnError> {
+    let mut fragments: Vec<(Fragment, Span)> = Vec::new();
+    let mut position = 0;
+    while let Some(token) = tokens.get(position) {
+        position += 1;
+        match &token.value {
+            MathValue::Num(num) => fragments.push((Fragment::value(negative_value(&num.to_string())), token.span)),
+            MathValue::Alge(al) | MathValue::Const(al) => fragments.push((Fragment::value(negative_value(al)), token.span)),
+            MathValue::Bool(value) => fragments.push((Fragment::value(value.to_string()), token.span)),
+            MathValue::Func(name, arity) => {
+                let (args, span) = pop_fragments(&mut fragments, *arity, name, token.span)?;
+                fragments.push((call(name, args), span));
+            },
+            MathValue::Op(op) => {
+                let operator = operators.operator(op).ok_or(RpnError::InvalidOperator { op: op.to_string(), span: token.span })?;
+                let (mut args, span) = pop_fragments(&mut fragments, operator.arity, op, token.span)?;
+                let fragment = match (args.pop(), args.pop()) {
+                    (Some(operand), None) => prefix(op, Some(operator), operand),
+                    (Some(right), Some(left)) => binary(op, Some(operator), left, right),
+                    _ => return Err(RpnError::InvalidOperator { op: op.to_string(), span: token.span }),
+                };
+                fragments.push((fragment, span));
+            },
+            MathValue::JumpUnless(_) => {
+                let (then, otherwise, end) = branches(tokens, position - 1)
+                    .ok_or(RpnError::InvalidOperator { op: "?".to_string(), span: token.span })?;
+                if depth >= MAX_DEPTH {
+                    return Err(RpnError::NestingLimit { span: token.span });
+                }
+                let (mut condition, span) = pop_fragments(&mut fragments, 1, "?", token.span)?;
+                let condition = condition.remove(0);
+                let (then, then_span) = convert_tokens(then, operators, depth + 1)?;
+                let (otherwise, otherwise_span) = convert_tokens(otherwise, operators, depth + 1)?;
+                fragments.push((conditional_fragment(condition, then, otherwise), span.join(then_span).join(otherwise_span)));
+                position = end;
+            },
+            // Jumps are only reached through their conditional
+            MathValue::Jump(_) => return Err(RpnError::InvalidOperator { op: ":".to_string(), span: token.span }),
+        }
+    }
+    match fragments.len() {
+        0 => Err(RpnError::TooShort { span: Span::default() }),
+        1 => Ok(fragments.remove(0)),
+        _ => {
+            let span = fragments.iter().map(|(_, span)| *span).reduce(Span::join).unwrap_or_default();
+            Err(RpnError::LeftoverOperands { span })
+        },
+    }
+}
+
+/// A function called with its arguments
+fn call(name: &str, args: Vec<Fragment>) -> Fragment {
+    let args = args.into_iter().map(|arg| arg.text).collect::<Vec<String>>();
+    Fragment::value(format!("{}({})", name, args.join(", ")))
+}
+
+/// A prefix operator before its operand. Without the operator from the table,
+/// any operand holding an operator is bracketed.
+fn prefix(op: &str, operator: Option<Operator>, operand: Fragment) -> Fragment {
+    // A prefix operand is already a value, and only tighter operators can go inside one
+    let needs_brackets = match operator {
+        Some(operator) => !operand.is_prefix() && operand.precedence() > Some(operator.precedence),
+        None => operand.operator.is_some(),
+    };
+    // Word operators such as 'not' are kept apart from a name after them
+    let symbol = match op {
+        symbol if is_identifier(symbol) => format!("{} ", symbol),
+        symbol => symbol.replace(NEGATE, "-"),
+    };
+    let text = format!("{}{}", symbol, operand.wrapped(needs_brackets));
+    Fragment { text, operator: Some(operator.unwrap_or(Operator::prefix(CONDITIONAL_PRECEDENCE - 1))) }
+}
+
+/// A binary operator between its operands. Without the operator from the table,
+/// any operand holding an operator is bracketed, as is the operator inside any other.
+fn binary(op: &str, operator: Option<Operator>, left: Fragment, right: Fragment) -> Fragment {
+    let (left_brackets, right_brackets) = match operator {
+        Some(operator) => {
+            let left_brackets = left.precedence().is_some_and(|precedence| {
+                precedence > operator.precedence ||
+                (precedence == operator.precedence && operator.associativity == Associativity::Right)
+            });
+            let right_brackets = !right.is_prefix() && right.precedence().is_some_and(|precedence| {
+                precedence > operator.precedence ||
+                (precedence == operator.precedence && operator.associativity == Associativity::Left)
+            });
+            (left_brackets, right_brackets)
+        },
+        None => (left.operator.is_some(), right.operator.is_some()),
+    };
+    let text = format!("{} {} {}", left.wrapped(left_brackets), op, right.wrapped(right_brackets));
+    Fragment { text, operator: Some(operator.unwrap_or(Operator::new(CONDITIONAL_PRECEDENCE - 1, Associativity::Left))) }
+}
+
+/// A conditional picking between its branches
+fn conditional_fragment(condition: Fragment, then: Fragment, otherwise: Fragment) -> Fragment {
+    // Only a conditional needs brackets as a condition, a branch can hold anything
+    let needs_brackets = condition.precedence() == Some(CONDITIONAL_PRECEDENCE);
+    let text = format!("{} ? {} : {}", condition.wrapped(needs_brackets), then.text, otherwise.text);
+    Fragment { text, operator: Some(Operator::new(CONDITIONAL_PRECEDENCE, Associativity::Right)) }
+}
+
+/// Writes an expression tree as infix, adding only the brackets needed to keep its order
+/// by the operators in the table. Operators not in the table bracket, and are bracketed by,
+/// any operand holding an operator.
+pub fn convert_expr_to_in_fix_with(expr: &Expr, operators: &OperatorTable) -> String {
+    expr.fold(&mut Print(operators)).text
+}
+
+/// Writes each part of an expression tree from the infix of its operands
+struct Print<'a>(&'a OperatorTable);
+impl Fold for Print<'_> {
+    type Output = Fragment;
+
+    fn number(&mut self, value: f64) -> Fragment {
+        Fragment::value(negative_value(&value.to_string()))
+    }
+    fn boolean(&mut self, value: bool) -> Fragment {
+        Fragment::value(value.to_string())
+    }
+    fn variable(&mut self, name: &str) -> Fragment {
+        Fragment::value(negative_value(name))
+    }
+    fn binary(&mut self, op: &str, left: Fragment, right: Fragment) -> Fragment {
+        binary(op, self.0.operator(op).filter(|operator| operator.arity == 2), left, right)
+    }
+    fn unary(&mut self, op: &str, operand: Fragment) -> Fragment {
+        prefix(op, self.0.operator(op).filter(|operator| operator.arity == 1), operand)
+    }
+    fn call(&mut self, name: &str, args: Vec<Fragment>) -> Fragment {
+        match (name, <[Fragment; 3]>::try_from(args)) {
+            (functions::CONDITIONAL, Ok([condition, then, otherwise])) => conditional_fragment(condition, then, otherwise),
+            (_, Ok(args)) => call(name, args.into()),
+            (_, Err(args)) => call(name, args),
+        }
+    }
+}
+
+/// Pops the operands of an operator or function, in the order they were written
+fn pop_fragments(fragments: &mut Vec<(Fragment, Span)>, count: usize, op: &str, span: Span) -> Result<(Vec<Fragment>, Span), RpnError> {
+    if fragments.len() < count {
+        return Err(RpnError::StackUnderflow { op: op.to_string(), span });
+    }
+    let popped = fragments.split_off(fragments.len() - count);
+    let span = popped.iter().map(|(_, span)| *span).fold(span, Span::join);
+    Ok((popped.into_iter().map(|(fragment, _)| fragment).collect(), span))
+}
+
+/// Writes the leading '-' of a negative value as ' ¬ ', which reads back as part of the value
+fn negative_value(text: &str) -> String {
+    let digits = text.trim_start_matches('-');
+    format!("{}{}", "¬".repeat(text.len() - digits.len()), digits)
+}
+
+#[cfg(test)]
+mod infix_tests {
+    use super::*;
+
+    fn to_infix(rpn: &str) -> String {
+        convert_post_to_in_fix(&postfix::parse(rpn).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn minimal_brackets() {
+        assert_eq!("1 + 2 * 3", to_infix("1 2 3 * +"));
+        assert_eq!("(1 + 2) * 3", to_infix("1 2 + 3 *"));
+        assert_eq!("8 - 4 - 2", to_infix("8 4 - 2 -"));
+        assert_eq!("8 - (4 - 2)", to_infix("8 4 2 - -"));
+        assert_eq!("2 ^ 3 ^ 2", to_infix("2 3 2 ^ ^"));
+        assert_eq!("(2 ^ 3) ^ 2", to_infix("2 3 ^ 2 ^"));
+        assert_eq!("a % b // c * d", to_infix("a b % c // d *"));
+    }
+    #[test]
+    fn negation() {
+        assert_eq!("-x ^ 2", to_infix("x 2 ^ ~"));
+        assert_eq!("(-x) ^ 2", to_infix("x ~ 2 ^"));
+        assert_eq!("2 ^ -x", to_infix("2 x ~ ^"));
+        assert_eq!("-(x + 1) - --5", to_infix("x 1 + ~ 5 ~ ~ -"));
+        assert_eq!("¬5 * ¬x", to_infix("-5 -x *"));
+    }
+    #[test]
+    fn comparisons() {
+        assert_eq!("x + 1 < y and not z", to_infix("x 1 + y < z not and"));
+        assert_eq!("(not (a or b)) == false", to_infix("a b or not false =="));
+        assert_eq!("a and b or c", to_infix("a b and c or"));
+    }
+    #[test]
+    fn conditionals() {
+        assert_eq!("x > 0 ? x : -x", to_infix("x 0 > ?2 x :2 x ~"));
+        assert_eq!("(a ? b : c) + 1", to_infix("a ?2 b :1 c 1 +"));
+        assert_eq!("(a ? b : c) ? d : e ? f : g", to_infix("a ?2 b :1 c ?2 d :5 e ?2 f :1 g"));
+        assert!(convert_post_to_in_fix(&postfix::parse("a ?5 b :1 c").unwrap()).is_err());
+        let nested = shunting_yard::convert_in_to_post_fix(&("a ? b : ".repeat(3000) + "c")).unwrap();
+        assert!(matches!(convert_post_to_in_fix(&nested), Err(RpnError::NestingLimit { .. })));
+    }
+    #[test]
+    fn functions() {
+        assert_eq!("max(a + 1, b) * sin(x)", to_infix("a 1 + b max x sin *"));
+        assert_eq!("2 * pi", to_infix("2 pi *"));
+    }
+    #[test]
+    fn malformed_stack() {
+        let underflow = convert_post_to_in_fix(&postfix::parse("1 +").unwrap()).unwrap_err();
+        assert_eq!(RpnError::StackUnderflow { op: "+".to_string(), span: Span::new(2, 3) }, underflow);
+        let leftover = convert_post_to_in_fix(&postfix::parse("1 2 3 +").unwrap()).unwrap_err();
+        assert_eq!(RpnError::LeftoverOperands { span: Span::new(0, 7) }, leftover);
+    }
+
+    /// infix -> rpn -> infix -> rpn gives the same rpn both times
+    fn round_trip(convert: fn(&str) -> Result<Stack, RpnError>) {
+        let inputs = [
+            "1+2*3-4/5", "(1+2)*(3-4)/5", "2^3^2", "(2^3)^2", "8-(4-2)-1", "8/(4/2)*(1/2)",
+            "-2^2", "(-2)^-2", "-(x+1) - --5", "(!5+!5) * !1", "!4.3a + (b^2-!10)*(x/(1/2))",
+            "a%b//c*(d%e)", "max(a, -b)^2", "sin(cos(x)^2)", "atan2(y - 1, -x) * log(2, x)^-2",
+            "2*pi*r", "e^(0.5*sin(-tau))", "-(a-b)^(c*-d)",
+            "x < 1 and not (y >= 2 or z != 3)", "not a == b or true", "(a > b) == (c <= d)",
+            "x > 0 ? x : -x", "a ? b : c ? d : e", "(a ? b : c) ? d : e", "1 + if(x, y, z) * 2",
+        ];
+        for input in inputs {
+            let rpn = convert(input).unwrap();
+            let infix = convert_post_to_in_fix(&rpn).unwrap();
+            assert_eq!(rpn.as_string(), convert(&infix).unwrap().as_string(), "{} -> {}", input, infix);
+        }
+    }
+    #[test]
+    fn round_trip_shunting_yard() {
+        round_trip(shunting_yard::convert_in_to_post_fix);
+    }
+    #[test]
+    fn round_trip_ast_tree() {
+        round_trip(ast_tree::convert_in_to_post_fix);
+    }
+}
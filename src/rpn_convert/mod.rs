@@ -5,115 +5,329 @@ use lazy_static::lazy_static;
 
 pub mod ast_tree;
 pub mod shunting_yard;
+pub mod evaluate;
+pub mod error;
+pub mod diagnostic;
+pub mod functions;
+pub mod context;
+pub mod postfix;
+pub mod infix;
+pub mod prefix;
+pub mod expr;
+pub mod derivative;
+pub mod simplify;
+pub mod options;
+pub mod operators;
+mod number;
+
+pub use error::{RpnError, Span};
+pub use context::{Context, ModuloMode};
+pub use expr::{Expr, Fold};
+pub use options::ParseOptions;
+pub use operators::OperatorTable;
+pub use evaluate::{Value, ValueType};
 
 #[derive(Debug, Clone)]
 pub enum MathValue {
     Num(f64),
     Alge(String),
-    Op(char),
+    /// An operator by its rpn symbol, such as `+`, `~` or `and`, or a bracket, comma, `?` or `:`
+    Op(String),
+    /// Function name and the number of arguments it was called with
+    Func(String, usize),
+    /// Named constant such as pi, solved to its value but written by name
+    Const(String),
+    /// `true` or `false`
+    Bool(bool),
+    /// Skips the next n tokens unless the value on top of the stack is true.
+    /// Starts the first branch of a conditional in rpn, written `?n`
+    JumpUnless(usize),
+    /// Skips the next n tokens, past the second branch of a conditional. Written `:n`
+    Jump(usize),
+}
+
+impl MathValue {
+    /// True if this is the operator, bracket or separator written as the symbol
+    pub fn is_op(&self, symbol: &str) -> bool {
+        matches!(self, MathValue::Op(op) if op == symbol)
+    }
+}
+
+/// A value and where it came from in the input
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub value: MathValue,
+    pub span: Span,
+}
+impl Token {
+    pub fn new(value: MathValue, span: Span) -> Token {
+        Token { value, span }
+    }
 }
 
-lazy_static! {
-    static ref pres_map: HashMap<char, u8> = HashMap::from([
-        ('^', 0),
-        ('*', 1),
-        ('/', 1),
-        ('+', 2),
-        ('-', 2)
-    ]);
+/// Which side an operator groups from when chained with itself,
+/// a - b - c is (a - b) - c but a ^ b ^ c is a ^ (b ^ c)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
 }
+
+/// Definition of an operator. The lower the precedence, the tighter
+/// the operator binds. Operators with an arity of 1 are prefix operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operator {
+    pub precedence: u8,
+    pub associativity: Associativity,
+    pub arity: usize,
+}
+impl Operator {
+    pub const fn new(precedence: u8, associativity: Associativity) -> Operator {
+        Operator { precedence, associativity, arity: 2 }
+    }
+
+    pub const fn prefix(precedence: u8) -> Operator {
+        Operator { precedence, associativity: Associativity::Right, arity: 1 }
+    }
+
+    /// True if this operator, arriving after `other`, should let `other` apply first
+    fn yields_to(&self, other: &Operator) -> bool {
+        other.precedence < self.precedence ||
+            (other.precedence == self.precedence && self.associativity == Associativity::Left)
+    }
+}
+
+/// Unary minus, written as '-' in operand position
+pub const NEGATE: &str = "~";
+/// Integer division
+pub const INT_DIV: &str = "//";
+/// Comparisons other than '<' and '>'
+pub const LESS_EQUAL: &str = "<=";
+pub const GREATER_EQUAL: &str = ">=";
+pub const EQUAL: &str = "==";
+pub const NOT_EQUAL: &str = "!=";
+/// Boolean operators, written as whole words
+pub const AND: &str = "and";
+pub const OR: &str = "or";
+pub const NOT: &str = "not";
+
+/// A conditional, `c ? a : b` or `if(c, a, b)`, binds more loosely than any operator
+pub const CONDITIONAL_PRECEDENCE: u8 = u8::MAX;
+
+/// How deeply brackets, calls, conditionals and operators can nest
+/// before parsing fails with `RpnError::NestingLimit`
+pub const MAX_DEPTH: usize = 128;
+
 trait Push {
-    fn push(&mut self, token: MathValue);
+    fn push(&mut self, token: Token);
 }
 
 pub struct Validate;
 impl Validate {
-    pub fn validate_input(input: &str) ->(bool, &str) {
-        // Remove spaces
-        let input = input.replace(" ", "");
-        if !Self::validate_len(&input) {
-            return (false, "Enter at least 3 elements");
+    /// Checks the input can be converted, returning the first problem found
+    pub fn validate_input(input: &str) -> Result<(), RpnError> {
+        Self::validate_input_with(input, &ParseOptions::default())
+    }
+
+    /// Checks the input can be converted when read with the options
+    pub fn validate_input_with(input: &str, options: &ParseOptions) -> Result<(), RpnError> {
+        match Self::validate_all_with(input, options).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
         }
-    
-        // Check every value is either in pres_map, alpha, digit or bracket
-        if !Self::validate_chars(&input){
-            return (false, "Invalid Char");
+    }
+
+    /// Checks the input can be converted, returning every problem found.
+    /// Problems are grouped by check, in the order the checks run.
+    pub fn validate_all(input: &str) -> Vec<RpnError> {
+        Self::validate_all_with(input, &ParseOptions::default())
+    }
+
+    /// Checks the input can be converted when read with the options, returning every problem found
+    pub fn validate_all_with(input: &str, options: &ParseOptions) -> Vec<RpnError> {
+        let mut errors: Vec<RpnError> = Vec::new();
+        if !Self::validate_len(input) {
+            errors.push(RpnError::TooShort { span: Span::new(0, input.len()) });
         }
     
+        // Check every value is either an operator, alpha, digit or bracket
+        errors.extend(Self::validate_chars(input, options.operators()));
+    
         // Check no sandwiched operators (a OP b)
-        if !Self::validate_sandwich_operators(&input) {
-            return (false, "Invalid order of operators");
-        }
+        errors.extend(Self::validate_sandwich_operators(input, options));
     
         // Check correct number of brackets
-        if !Self::validate_parentheses(&input) {
-            return (false, "Invalid order of parentheses");
-        }
+        errors.extend(Self::validate_parentheses(input));
     
-        (true, "Is_valid")
+        errors
     }
 
     fn char_contained_in(ch: char, haystack: &str) -> bool {
         haystack.chars().any(|c| c == ch)
     }
 
+    /// Chars of the input with their byte offset, skipping spaces
+    fn significant_chars(input: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+        input.char_indices().filter(|(_, c)| !c.is_whitespace())
+    }
+
+    /// A single value, such as `x` or `-x`, is a whole expression
     fn validate_len(input: &str) -> bool{
-        let input = input.replace(" ", "");
-        if input.len() < 3 {
-            false
-        } else {
-            true
-        }
+        Self::significant_chars(input).next().is_some()
     }
 
-    fn validate_chars(input: &str) -> bool {
-        if input.chars().any(|c| {
+    fn validate_chars(input: &str, operators: &OperatorTable) -> Vec<RpnError> {
+        Self::significant_chars(input).filter(|(_, c)| {
             !(
-            c.is_alphabetic() || 
+            is_name_char(*c) || 
             c.is_ascii_digit() || 
-            Self::char_contained_in(c, "().!¬") ||
-            pres_map.contains_key(&c)
-            )}) 
-        {
-            false
-        } else {
-            true
-        }
+            Self::char_contained_in(*c, "(),.!¬?:") ||
+            operators.writes_with(*c)
+            )})
+            .map(|(index, ch)| RpnError::UnknownChar { ch, span: Span::of_char(index, ch) })
+            .collect()
     }
 
-    // Change for Sliding Window
-    fn validate_sandwich_operators(input: &str) -> bool {
-        let mut iter = input.chars().peekable();
-        while let Some(first) = &iter.next() {
-            if let Some(second) = iter.peek() {
-                if pres_map.contains_key(first) && pres_map.contains_key(second) { 
-                    return false;
-                }
+    /// Checks every binary operator sits between two operands, every
+    /// prefix operator comes before one and commas only separate arguments
+    fn validate_sandwich_operators(input: &str, options: &ParseOptions) -> Vec<RpnError> {
+        let stack = match Stack::tokenize(input, options) {
+            Ok(stack) => stack,
+            Err(error) => return vec![error],
+        };
+        let mut errors: Vec<RpnError> = Vec::new();
+        let mut after_operand = false;
+        // Whether each open bracket belongs to a function call
+        let mut brackets: Vec<bool> = Vec::new();
+        // Each '?' still waiting for its ':'
+        let mut conditions: Vec<Span> = Vec::new();
+        let mut after_function = false;
+        let mut after_unknown = false;
+        for (position, token) in stack.elements.iter().enumerate() {
+            let opens_call = std::mem::take(&mut after_function);
+            // Values next to an unknown char are not reported again
+            let operand_before = after_operand && !std::mem::take(&mut after_unknown);
+            let op = match &token.value {
+                MathValue::Op(op) => op.as_str(),
+                // Always followed by its bracket
+                MathValue::Func(..) => {
+                    Self::check_operand_position(operand_before, token.span, &mut errors);
+                    after_operand = false;
+                    after_function = true;
+                    continue;
+                },
+                _ => {
+                    Self::check_operand_position(operand_before, token.span, &mut errors);
+                    after_operand = true;
+                    continue;
+                },
+            };
+            match options.operators().operator(op) {
+                Some(operator) => {
+                    // Binary operators need an operand before them, prefix operators must not have one
+                    if after_operand != (operator.arity == 2) {
+                        errors.push(RpnError::DanglingOperator { op: op.to_string(), span: token.span });
+                    }
+                    after_operand = false;
+                },
+                None if op == "," => {
+                    if !after_operand || brackets.last() != Some(&true) {
+                        errors.push(RpnError::DanglingOperator { op: op.to_string(), span: token.span });
+                    }
+                    after_operand = false;
+                },
+                None if op == "(" => {
+                    brackets.push(opens_call);
+                    after_operand = false;
+                },
+                None if op == "?" || op == ":" => {
+                    let unmatched = op == ":" && conditions.pop().is_none();
+                    if !after_operand || unmatched {
+                        errors.push(RpnError::DanglingOperator { op: op.to_string(), span: token.span });
+                    }
+                    if op == "?" {
+                        conditions.push(token.span);
+                    }
+                    after_operand = false;
+                },
+                None if op == ")" => {
+                    let closes_call = brackets.pop() == Some(true);
+                    match position.checked_sub(1).map(|previous| &stack.elements[previous]) {
+                        _ if after_operand => (),
+                        // A call without arguments
+                        Some(previous) if previous.value.is_op("(") && closes_call => (),
+                        Some(Token { value: MathValue::Op(previous), span }) if previous != "(" => {
+                            if !errors.iter().any(|error| error.span() == *span) {
+                                errors.push(RpnError::DanglingOperator { op: previous.to_string(), span: *span });
+                            }
+                        },
+                        _ => errors.push(RpnError::ExpectedValue { span: token.span }),
+                    }
+                    after_operand = true;
+                },
+                // Part of an operator, but not one on its own, such as '<' when only '<<' is known
+                None if op.chars().any(|c| options.operators().writes_with(c)) => {
+                    errors.push(RpnError::InvalidOperator { op: op.to_string(), span: token.span });
+                    after_operand = false;
+                },
+                // Unknown chars are reported by validate_chars
+                None => {
+                    after_operand = true;
+                    after_unknown = true;
+                },
+            }
+        }
+        // Nothing after the last operator
+        if let Some(Token { value: MathValue::Op(op), span }) = stack.elements.last() {
+            let is_operator = options.operators().has_operator(op) || op == "?" || op == ":";
+            if is_operator && !errors.iter().any(|error| error.span() == *span) {
+                errors.push(RpnError::DanglingOperator { op: op.to_string(), span: *span });
             }
         }
-        true
+        // Conditions without their second branch
+        let unfinished: Vec<RpnError> = conditions.into_iter()
+            .filter(|span| !errors.iter().any(|error| error.span() == *span))
+            .map(|span| RpnError::DanglingOperator { op: "?".to_string(), span })
+            .collect();
+        errors.extend(unfinished);
+        // Only signs, such as a lone '+', leave no value at all
+        if stack.elements.is_empty() && Self::validate_len(input) {
+            errors.push(RpnError::ExpectedValue { span: Span::new(input.len(), input.len()) });
+        }
+        errors
     }
 
-    fn validate_parentheses(input: &str) -> bool {
-        let mut parentheses:Vec<char> = Vec::new();
-        for bracket in input.chars().filter(|b| Self::char_contained_in(*b, "()")) {
+    /// Values and calls need an operator between them and the operand before,
+    /// so `1 2` is not taken as one of them
+    fn check_operand_position(after_operand: bool, span: Span, errors: &mut Vec<RpnError>) {
+        if after_operand {
+            errors.push(RpnError::LeftoverOperands { span });
+        }
+    }
+
+    fn validate_parentheses(input: &str) -> Vec<RpnError> {
+        let mut errors: Vec<RpnError> = Vec::new();
+        let mut parentheses: Vec<Span> = Vec::new();
+        for (index, bracket) in Self::significant_chars(input).filter(|(_, b)| Self::char_contained_in(*b, "()")) {
+            let span = Span::of_char(index, bracket);
             match bracket {
-                '(' => parentheses.push(bracket),
-                ')' => {
+                '(' => parentheses.push(span),
+                _ => {
                     if parentheses.pop().is_none() {
-                        return false;
+                        errors.push(RpnError::MismatchedParenthesis { span });
                     }
                 },
-                _ => return false,
             }
         }
-        parentheses.is_empty()
+        // Brackets never closed, left to right
+        errors.extend(parentheses.into_iter().map(|span| RpnError::MismatchedParenthesis { span }));
+        errors
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Stack {
-    elements: Vec<MathValue>,
+    elements: Vec<Token>,
 }
 impl fmt::Display for Stack {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -122,7 +336,7 @@ impl fmt::Display for Stack {
 }
 
 impl Push for Stack {
-    fn push(&mut self, token: MathValue) {
+    fn push(&mut self, token: Token) {
         self.elements.push(token);
     }  
 }
@@ -133,44 +347,167 @@ impl Stack {
             elements: Vec::new(),
         }
     }   
-    pub fn try_from(input: &str) -> Result<Stack, Box<dyn Error>> {
-        let (is_valid, msg) = Validate::validate_input(input);
-        if !is_valid {
-            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, msg)));
-        }
+    pub fn try_from(input: &str) -> Result<Stack, RpnError> {
+        Self::try_from_with(input, &ParseOptions::default())
+    }
+
+    pub fn try_from_with(input: &str, options: &ParseOptions) -> Result<Stack, RpnError> {
+        Validate::validate_input_with(input, options)?;
+        Self::tokenize(input, options)
+    }
+
+    /// Splits the input into tokens without validating it.
+    /// Any char which is not part of a value becomes an operator.
+    fn tokenize(input: &str, options: &ParseOptions) -> Result<Stack, RpnError> {
         let mut stack = Stack::new();
         let mut number_as_string = String::from("");
-        let mut digit_tracker = false;
+        let mut number_span: Option<Span> = None;
 
-        // True for alge, else false
-        let conversion_type_is_alge = input.chars().any(|c| c.is_alphabetic());
+        // Number literal the run starts with, as the run length at its end and its value
+        let mut literal: Option<(usize, f64)> = None;
 
-        for token in input.chars() {
-            if handle_non_op_token(&token, &mut digit_tracker, &mut number_as_string) {
+        // Spaces are skipped, but end a value
+        let chars: Vec<(usize, char)> = input.char_indices().filter(|(_, c)| !c.is_whitespace()).collect();
+        // Where each of those chars is among all the chars, as number literals are read from the input itself
+        let original: Vec<char> = input.chars().collect();
+        let offsets: Vec<usize> = original.iter().enumerate().filter(|(_, c)| !c.is_whitespace()).map(|(offset, _)| offset).collect();
+        let mut position = 0;
+        while let Some(&(index, token)) = chars.get(position) {
+            position += 1;
+            let span = Span::of_char(index, token);
+            // A space ends a value, so `1 2` is two values rather than 12. Only ' ! ' and ' ¬ '
+            // carry over one, as they make the value after them negative.
+            let after_space = offsets[position - 1] > 0 && original[offsets[position - 1] - 1].is_whitespace();
+            if after_space && !number_as_string.chars().all(|c| c == '-') {
+                if let Some(value_span) = number_span.take() {
+                    stack.push_run(input, std::mem::take(&mut number_as_string), value_span, literal.take(), token == '(', options)?;
+                }
+            }
+            // Numbers are read whole where a value starts, so an exponent keeps its sign.
+            // A literal is never split by a space, so `1e + 1` is not 1e+1.
+            if number_as_string.chars().all(|c| c == '-') {
+                let start = position - 1;
+                let literal_chars = &original[offsets[start]..];
+                if let Some(lexed) = number::lex(literal_chars) {
+                    let length = lexed.map_or_else(|length| length, |(length, _)| length);
+                    let text: String = literal_chars[..length].iter().collect();
+                    let literal_span = Span::new(index, index + text.len());
+                    let (_, value) = lexed.map_err(|_| RpnError::NumberParse { text: text.clone(), span: literal_span })?;
+                    let negative = number_as_string.len() % 2 == 1;
+                    number_as_string.push_str(&text);
+                    literal = Some((number_as_string.len(), if negative { -value } else { value }));
+                    number_span = Some(number_span.map_or(literal_span, |s| s.join(literal_span)));
+                    position = start + length;
+                    continue;
+                }
+            }
+            // Operators are matched longest first, so '**' is one operator rather than two
+            // and '!=' is not a negative value. Word operators such as 'and' must be whole words.
+            let operator = match token {
+                c if is_name_char(c) => word_operator(input, index, options.operators()),
+                _ => options.operators().lex(&input[index..]),
+            };
+            if operator.is_none() && handle_non_op_token(&token, &mut number_as_string) {
+                number_span = Some(number_span.map_or(span, |s| s.join(span)));
                 continue;
             }
-            if digit_tracker {
-                push_conversion_type(&mut stack, number_as_string, conversion_type_is_alge)?;
-                digit_tracker = false;
+            if let Some(value_span) = number_span.take() {
+                stack.push_run(input, number_as_string, value_span, literal.take(), token == '(', options)?;
                 number_as_string = "".to_string();
             }
-            stack.push(MathValue::Op(token));
-            
+            let (op, length) = operator.unwrap_or((&input[index..index + token.len_utf8()], token.len_utf8()));
+            let span = Span::new(index, index + length);
+            while chars.get(position).is_some_and(|(next, _)| *next < span.end) {
+                position += 1;
+            }
+            // A sign where a value should be is unary, so '+' does nothing
+            // and '-' becomes a negation
+            match op {
+                "+" if stack.expects_operand() => (),
+                "-" if stack.expects_operand() => stack.push(Token::new(MathValue::Op(NEGATE.to_string()), span)),
+                "(" => {
+                    stack.push_implicit_multiplication(span, options);
+                    stack.push(Token::new(MathValue::Op(op.to_string()), span));
+                },
+                _ => stack.push(Token::new(MathValue::Op(op.to_string()), span)),
+            }
         }
-        if !number_as_string.is_empty() {
-            if conversion_type_is_alge {}
-            push_conversion_type(&mut stack, number_as_string, conversion_type_is_alge)?;
+        if let Some(value_span) = number_span {
+            stack.push_run(input, number_as_string, value_span, literal, false, options)?;
         }
         Ok(stack)        
     }
 
-    pub fn iter(&self) -> StackIter {
+    /// Pushes a run of value chars, as a function if it names one straight before a bracket.
+    /// A run that is only a number literal is pushed as its value. With implicit
+    /// multiplication a literal is first split from the name written after it.
+    fn push_run(&mut self, input: &str, mut run: String, span: Span, literal: Option<(usize, f64)>, before_bracket: bool, options: &ParseOptions) -> Result<(), RpnError> {
+        match literal {
+            Some((length, value)) if length == run.len() => {
+                self.push_implicit_multiplication(span, options);
+                self.push(Token::new(MathValue::Num(value), span));
+                return Ok(());
+            },
+            Some((length, _)) if options.implicit_multiplication() => {
+                let name = run.split_off(length);
+                // Byte offset of the first char of the name, as spaces inside values are skipped
+                let split = input[span.start..].char_indices()
+                    .filter(|(_, c)| !c.is_whitespace())
+                    .nth(run.chars().count())
+                    .map_or(span.end, |(index, _)| span.start + index);
+                self.push_run(input, run, Span::new(span.start, split), literal, false, options)?;
+                return self.push_run(input, name, Span::new(split, span.end), None, before_bracket, options);
+            },
+            _ => (),
+        }
+        self.push_implicit_multiplication(span, options);
+        // Constants are values even before a bracket when it means multiplication
+        let is_constant = options.implicit_multiplication() && context::builtin_constant(&run).is_some();
+        if before_bracket && is_identifier(&run) && !is_constant {
+            self.push(Token::new(MathValue::Func(run, 0), span));
+            Ok(())
+        } else {
+            push_value(self, run, span)
+        }
+    }
+
+    /// With implicit multiplication, adds a '*' between a finished operand and
+    /// the value or bracket starting at the span
+    fn push_implicit_multiplication(&mut self, span: Span, options: &ParseOptions) {
+        let ends_operand = self.elements.last().is_some_and(|token| {
+            matches!(token.value, MathValue::Num(_) | MathValue::Alge(_) | MathValue::Const(_)) || token.value.is_op(")")
+        });
+        if options.implicit_multiplication() && ends_operand {
+            self.push(Token::new(MathValue::Op("*".to_string()), Span::new(span.start, span.start)));
+        }
+    }
+
+    /// True if the next token would be in operand position, at the start,
+    /// after an opening bracket or after another operator
+    fn expects_operand(&self) -> bool {
+        match self.elements.last() {
+            None => true,
+            Some(Token { value: MathValue::Op(op), .. }) => op != ")",
+            Some(_) => false,
+        }
+    }
+
+    pub fn iter(&self) -> StackIter<'_> {
         StackIter { stack: self, index: 0}
     }
 
-    pub fn len(self) -> usize {
+    /// Tokens with their spans in the original input
+    pub fn tokens(&self) -> &[Token] {
+        &self.elements
+    }
+
+    pub fn len(&self) -> usize {
         self.elements.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
     
     pub fn as_string(&self) -> String {
         self.iter().map(|el| 
@@ -178,19 +515,23 @@ impl Stack {
                 MathValue::Num(num) => num.to_string(),
                 MathValue::Alge(al) => al.to_string(),
                 MathValue::Op(op) => op.to_string(),
+                MathValue::Func(name, _) => name.to_string(),
+                MathValue::Const(name) => name.to_string(),
+                MathValue::Bool(value) => value.to_string(),
+                MathValue::JumpUnless(skip) => format!("?{}", skip),
+                MathValue::Jump(skip) => format!(":{}", skip),
             }
         ).collect::<Vec<String>>().join(" ")
     }
 
-    fn pop(&mut self) -> Option<MathValue> {
+    fn pop(&mut self) -> Option<Token> {
         self.elements.pop()
     }
     fn peak(&mut self) -> Option<&MathValue> {
-        if self.elements.is_empty(){
-            None
-        } else {
-            self.elements.last()
-        }
+        self.elements.last().map(|token| &token.value)
+    }
+    fn peak_token(&self) -> Option<&Token> {
+        self.elements.last()
     }
 }
 
@@ -205,7 +546,7 @@ impl<'a> Iterator for StackIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.stack.elements.len() {
             let result = Some(
-                &self.stack.elements[self.index]
+                &self.stack.elements[self.index].value
             );
             self.index += 1;
             result
@@ -216,40 +557,98 @@ impl<'a> Iterator for StackIter<'a> {
 
 }
 
-fn handle_non_op_token(token: &char, digit_tracker: &mut bool, number_as_string: &mut String) -> bool {
+fn handle_non_op_token(token: &char, number_as_string: &mut String) -> bool {
     match *token {
         '0'..='9' | '.' => {
             number_as_string.push(*token);
-            *digit_tracker = true;
             true 
         },
-        'a'..='z' => {
+        c if is_name_char(c) => {
             number_as_string.push(*token);
-            *digit_tracker = true;
             true
         },
         '!' | '¬' => {
             number_as_string.push('-');
-            *digit_tracker = true;
             true
         }
         _ => false
     }
 }
 
-fn push_conversion_type<T: Push>(target: &mut T, value: String, conversion_type: bool) -> Result<(), Box<dyn Error>> {
-    if conversion_type {
-        target.push(MathValue::Alge(value));
+/// Writes a conditional in rpn, as its condition followed by jumps around each branch
+/// so only the branch taken is evaluated. The spans are those of its `?` and `:`.
+fn conditional(condition: Vec<Token>, then: Vec<Token>, otherwise: Vec<Token>, spans: (Span, Span)) -> Vec<Token> {
+    let mut rpn = condition;
+    rpn.push(Token::new(MathValue::JumpUnless(then.len() + 1), spans.0));
+    rpn.extend(then);
+    rpn.push(Token::new(MathValue::Jump(otherwise.len()), spans.1));
+    rpn.extend(otherwise);
+    rpn
+}
+
+/// The two branches of the conditional whose `JumpUnless` is at the position, and
+/// the position just after the conditional. None if the jumps don't fit the tokens
+fn branches(tokens: &[Token], position: usize) -> Option<(&[Token], &[Token], usize)> {
+    let MathValue::JumpUnless(skip) = tokens.get(position)?.value else {
+        return None;
+    };
+    let jump = position.checked_add(skip)?;
+    let MathValue::Jump(length) = tokens.get(jump)?.value else {
+        return None;
+    };
+    let end = jump.checked_add(length)?.checked_add(1).filter(|end| *end <= tokens.len())?;
+    Some((&tokens[position + 1..jump], &tokens[jump + 1..end], end))
+}
+
+/// The word operator, such as `and`, written as the whole word starting at the byte offset
+fn word_operator<'a>(input: &str, index: usize, operators: &'a OperatorTable) -> Option<(&'a str, usize)> {
+    let is_word_char = |c: char| is_name_char(c) || c.is_ascii_digit();
+    if input[..index].chars().next_back().is_some_and(is_word_char) {
+        return None;
+    }
+    let word = input[index..].split(|c: char| !is_word_char(c)).next()?;
+    operators.word(word).map(|op| (op, word.len()))
+}
+
+/// True for chars that can start a name: any letter, including uppercase
+/// and other scripts such as θ, or an underscore
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+/// True if the text is a name, a letter or underscore followed by letters,
+/// underscores or ascii digits, such as `flow_rate2`, `Vmax` or `θ`
+pub fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(is_name_char) && chars.all(|c| is_name_char(c) || c.is_ascii_digit())
+}
+
+/// Types each value on its own, so numbers stay numbers beside variables.
+/// Any value containing a name char is algebraic, unless it names a constant or is `true` or `false`.
+fn push_value<T: Push>(target: &mut T, value: String, span: Span) -> Result<(), RpnError> {
+    if let Ok(value) = value.parse::<bool>() {
+        target.push(Token::new(MathValue::Bool(value), span));
+    } else if context::builtin_constant(&value).is_some() {
+        target.push(Token::new(MathValue::Const(value), span));
+    } else if value.chars().any(is_name_char) {
+        target.push(Token::new(MathValue::Alge(value), span));
+    } else if value.chars().all(|c| c == '-') {
+        // ' ! ' or ' ¬ ' with no number or name after it to make negative
+        return Err(RpnError::ExpectedValue { span });
     } else {
-        target.push(MathValue::Num(value.parse::<f64>()?));
+        match value.parse::<f64>() {
+            Ok(num) => target.push(Token::new(MathValue::Num(num), span)),
+            Err(_) => return Err(RpnError::NumberParse { text: value, span }),
+        }
     }
     Ok(())
 }
 
 // Unit tests
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod validate_input_tests {
-    use super::Validate;
+    use super::{Validate, RpnError, Span, ParseOptions, OperatorTable, Operator, Associativity, is_identifier, Stack, NEGATE};
     #[test]
     fn less_than_3() {
         let input_true = "2a+3.1";
@@ -258,18 +657,110 @@ mod validate_input_tests {
         assert_eq!(false, Validate::validate_len(input_false));
     }
     #[test]
+    fn single_values() {
+        for input in ["y", "-x", "!x", "2", " e "] {
+            assert_eq!(Ok(()), Validate::validate_input(input), "{}", input);
+        }
+        assert_eq!(Err(RpnError::TooShort { span: Span::new(0, 2) }), Validate::validate_input("  "));
+        assert_eq!(Err(RpnError::ExpectedValue { span: Span::new(1, 1) }), Validate::validate_input("+"));
+        assert_eq!(Err(RpnError::LeftoverOperands { span: Span::new(2, 3) }), Validate::validate_input("x y"));
+    }
+    #[test]
     fn invalid_char() {
         let input_true = "3.1+8a";
         let input_false = "3@5+7*(8+4)";
-        assert_eq!(true, Validate::validate_chars(input_true));
-        assert_eq!(false, Validate::validate_chars(input_false));
+        assert_eq!(true, Validate::validate_chars(input_true, &OperatorTable::new()).is_empty());
+        assert_eq!(false, Validate::validate_chars(input_false, &OperatorTable::new()).is_empty());
+    }
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("log10"));
+        assert!(is_identifier("flow_rate2"));
+        assert!(is_identifier("Vmax"));
+        assert!(is_identifier("_θ1"));
+        assert!(!is_identifier("10log"));
+        assert!(!is_identifier("-sin"));
+        assert!(!is_identifier("d1.5"));
+        assert_eq!(Ok(()), Validate::validate_input("X + Vmax_2"));
+    }
+    #[test]
+    fn spaces_end_values() {
+        let leftover = |start, end| Err(RpnError::LeftoverOperands { span: Span::new(start, end) });
+        assert_eq!(leftover(2, 4), Validate::validate_input("1 .5 + x"));
+        assert_eq!(leftover(2, 4), Validate::validate_input("1 e3 + x"));
+        assert_eq!(leftover(4, 6), Validate::validate_input("1_0 00 + x"));
+        assert_eq!(leftover(2, 5), Validate::validate_input("2 sin(x)"));
+        assert_eq!(leftover(3, 4), Validate::validate_input("(1)2 + x"));
+        assert_eq!(Ok(()), Validate::validate_input("! 5 + sin (x)"));
+        // With implicit multiplication they are multiplied instead
+        let mut options = ParseOptions::new();
+        options.set_implicit_multiplication(true);
+        let tokens = |input: &str| Stack::try_from_with(input, &options).unwrap().as_string();
+        assert_eq!("1 * 0.5 + x", tokens("1 .5 + x"));
+        assert_eq!("3 * e - 5", tokens("3 e-5"));
+        assert_eq!("2 * e + 1", tokens("2 e + 1"));
+    }
+    #[test]
+    fn malformed_numbers() {
+        let error = |text: &str, start, end| Err(RpnError::NumberParse { text: text.to_string(), span: Span::new(start, end) });
+        assert_eq!(error("1.2.3", 4, 9), Validate::validate_input("2 + 1.2.3"));
+        assert_eq!(error("0b102", 0, 5), Validate::validate_input("0b102 * x"));
+        assert_eq!(Ok(()), Validate::validate_input("6.02E23 * 0x1F"));
+        assert_eq!(error("0x", 0, 2), Validate::validate_input("0x + 1"));
+        assert_eq!(error("0b2", 0, 3), Validate::validate_input("0b2 + 1"));
+        assert_eq!(error("0x1G", 0, 4), Validate::validate_input("0x1G + 1"));
+        assert_eq!(error("1_", 0, 2), Validate::validate_input("1_ + 2"));
+        assert_eq!(error("1__0", 0, 4), Validate::validate_input("1__0 + 2"));
+        assert_eq!(error("1e5e5", 0, 5), Validate::validate_input("1e5e5 + 1"));
+        assert_eq!(error(".e5", 4, 7), Validate::validate_input("2 + .e5"));
+        assert_eq!(error("00x1", 0, 4), Validate::validate_input("00x1 + 1"));
     }
     #[test]
     fn invalid_sandwich_operators() {
         let input_true = "2.1+5a-3*(5-2)";
-        let input_false = "2++5-3*(5--2)";
-        assert_eq!(true,  Validate::validate_sandwich_operators(input_true));
-        assert_eq!(false, Validate::validate_sandwich_operators(input_false));
+        let input_false = "2+*5-3*(5-/2)";
+        assert_eq!(true,  Validate::validate_sandwich_operators(input_true, &ParseOptions::default()).is_empty());
+        assert_eq!(false, Validate::validate_sandwich_operators(input_false, &ParseOptions::default()).is_empty());
+    }
+    #[test]
+    fn operators_before_closing_brackets() {
+        let dangling = |op: &str, start, end| Err(RpnError::DanglingOperator { op: op.to_string(), span: Span::new(start, end) });
+        assert_eq!(dangling("+", 2, 3), Validate::validate_input("(1+)*2"));
+        assert_eq!(dangling(NEGATE, 1, 2), Validate::validate_input("(-)+1"));
+        assert_eq!(dangling("^", 7, 8), Validate::validate_input("max(x,x^)"));
+        assert_eq!(dangling("and", 3, 6), Validate::validate_input("(1 and) or x"));
+        assert_eq!(dangling("not", 3, 6), Validate::validate_input("2*(not)"));
+        assert_eq!(dangling(",", 5, 6), Validate::validate_input("max(x,)"));
+        assert_eq!(Err(RpnError::ExpectedValue { span: Span::new(1, 2) }), Validate::validate_input("()+1"));
+        assert_eq!(Ok(()), Validate::validate_input("f() + 1"));
+    }
+    #[test]
+    fn multi_char_operators() {
+        assert_eq!(Ok(()), Validate::validate_input("2**3 // 2"));
+        assert_eq!(Err(RpnError::DanglingOperator { op: "*".to_string(), span: Span::new(4, 5) }), Validate::validate_input("2 * * 3"));
+        let mut operators = OperatorTable::new();
+        operators.add("|>", Operator::new(4, Associativity::Left), |args| args[0]).unwrap();
+        let mut options = ParseOptions::new();
+        options.set_operators(operators);
+        assert_eq!(Ok(()), Validate::validate_input_with("1 |> 2", &options));
+        assert_eq!(Err(RpnError::InvalidOperator { op: "|".to_string(), span: Span::new(2, 3) }), Validate::validate_input_with("1 | 2", &options));
+    }
+    #[test]
+    fn comparisons() {
+        assert_eq!(Ok(()), Validate::validate_input("pressure < 100 and temp >= 0"));
+        assert_eq!(Ok(()), Validate::validate_input("not(x != !1) or y == true"));
+        assert_eq!(Err(RpnError::InvalidOperator { op: "=".to_string(), span: Span::new(2, 3) }), Validate::validate_input("x = 1"));
+        assert_eq!(Err(RpnError::DanglingOperator { op: "and".to_string(), span: Span::new(2, 5) }), Validate::validate_input("x and"));
+        assert_eq!(Err(RpnError::DanglingOperator { op: "not".to_string(), span: Span::new(2, 5) }), Validate::validate_input("x not y"));
+    }
+    #[test]
+    fn conditionals() {
+        assert_eq!(Ok(()), Validate::validate_input("x > 0 ? x : -x"));
+        assert_eq!(Ok(()), Validate::validate_input("a ? b ? c : d : if(e, f, g)"));
+        let dangling = |op: &str, start, end| Err(RpnError::DanglingOperator { op: op.to_string(), span: Span::new(start, end) });
+        assert_eq!(dangling("?", 2, 3), Validate::validate_input("a ? b"));
+        assert_eq!(dangling(":", 2, 3), Validate::validate_input("a : b"));
+        assert_eq!(dangling("?", 0, 1), Validate::validate_input("? a : b"));
     }
     #[test]
     fn invalid_params() { 
@@ -277,22 +768,33 @@ mod validate_input_tests {
         let input_false = "(2+3)^2 -(3+(4^32.3-1)+x^2))";
         let input_false_2 = "(()";
         let input_false_3 = "))((";
-        assert_eq!(true, Validate::validate_parentheses(input_true));        
-        assert_eq!(false, Validate::validate_parentheses(input_false));
-        assert_eq!(false, Validate::validate_parentheses(input_false_2));
-        assert_eq!(false, Validate::validate_parentheses(input_false_3));
+        assert_eq!(true, Validate::validate_parentheses(input_true).is_empty());        
+        assert_eq!(false, Validate::validate_parentheses(input_false).is_empty());
+        assert_eq!(false, Validate::validate_parentheses(input_false_2).is_empty());
+        assert_eq!(false, Validate::validate_parentheses(input_false_3).is_empty());
+    }
+    #[test]
+    fn validate_all_reports_every_problem() {
+        let errors = Validate::validate_all("(2@3 +* 4) * (5 # 6 -/1");
+        assert_eq!(vec![
+            RpnError::UnknownChar { ch: '@', span: Span::new(2, 3) },
+            RpnError::UnknownChar { ch: '#', span: Span::new(16, 17) },
+            RpnError::DanglingOperator { op: "*".to_string(), span: Span::new(6, 7) },
+            RpnError::DanglingOperator { op: "/".to_string(), span: Span::new(21, 22) },
+            RpnError::MismatchedParenthesis { span: Span::new(13, 14) },
+        ], errors);
     }
     // Integration Test
     #[test]
     fn validate_input_integration() {
-        assert_eq!((true, "Is_valid"), Validate::validate_input("2+5-1/7*2^(2-1)+a21"));
+        assert_eq!(Ok(()), Validate::validate_input("2+5-1/7*2^(2-1)+a21"));
     }
 }
 
 #[cfg(test)]
 mod rpn_convert_unit_tests {
     use super::*;
-    type RpnReturn = Result<Stack, Box<dyn Error>>;
+    type RpnReturn = Result<Stack, RpnError>;
 
     pub fn num_simple(func: fn(&str) -> RpnReturn) {
         assert_eq!("42 4234 4 234 - 43 43 * + * - 10 -", func("42 - 4234 * (4-234 + (43*43)) - 10").unwrap().as_string());
@@ -312,5 +814,126 @@ mod rpn_convert_unit_tests {
 
     pub fn alge_complex(func: fn(&str) -> RpnReturn) {
         assert_eq!("x 87.31 + x 31.23 - *", func("(x + 87.31)*(x-31.23)").unwrap().as_string());
+    }
+
+    pub fn unary(func: fn(&str) -> RpnReturn) {
+        assert_eq!("3 2 ~ *", func("3*-2").unwrap().as_string());
+        assert_eq!("2 2 ^ ~ 2 3 ~ ^ +", func("-2^2 + 2^-3").unwrap().as_string());
+        assert_eq!("x 1 + ~ 5 ~ ~ -", func("-(x+1) - --5").unwrap().as_string());
+        assert_eq!("4 x * 2 +", func("+4*x + +2").unwrap().as_string());
+    }
+
+    pub fn functions(func: fn(&str) -> RpnReturn) {
+        assert_eq!("x sin", func("sin(x)").unwrap().as_string());
+        assert_eq!("a b max", func("max(a, b)").unwrap().as_string());
+        assert_eq!("2 x 1 + 3 ^ y min log sqrt 4 *", func("sqrt(log(2, min((x+1)^3, y))) * 4").unwrap().as_string());
+        assert_eq!("x ~ abs ~ 2 atan2", func("atan2(-abs(-x), 2)").unwrap().as_string());
+    }
+
+    pub fn constants(func: fn(&str) -> RpnReturn) {
+        assert_eq!("2 pi * 3 *", func("2*pi*3").unwrap().as_string());
+        assert_eq!("e 0.5 sin ^", func("e^sin(0.5)").unwrap().as_string());
+        assert_eq!("tau 2.5 * 1 -", func("tau * 2.50 - 1").unwrap().as_string());
+    }
+
+    pub fn modulo(func: fn(&str) -> RpnReturn) {
+        assert_eq!("7 3 % 2 *", func("7 % 3 * 2").unwrap().as_string());
+        assert_eq!("1 7 2 // +", func("1 + 7 // 2").unwrap().as_string());
+        assert_eq!("9 2 // 4 % 2 ^ 3 1 ~ % /", func("(9//2%4)^2 / (3 % -1)").unwrap().as_string());
+    }
+
+    pub fn implicit_multiplication(func: fn(&str, &ParseOptions) -> RpnReturn) {
+        let mut options = ParseOptions::new();
+        options.set_implicit_multiplication(true);
+        assert_eq!("2 x * 1 +", func("2x+1", &options).unwrap().as_string());
+        assert_eq!("3 x 1 + *", func("3(x+1)", &options).unwrap().as_string());
+        assert_eq!("a b + a b - *", func("(a+b)(a-b)", &options).unwrap().as_string());
+        assert_eq!("2 pi * r 1 + *", func("2pi(r+1)", &options).unwrap().as_string());
+        assert_eq!("2 x sin * x cos *", func("2sin(x)cos(x)", &options).unwrap().as_string());
+        assert_eq!("2 ~ x 2 ^ * -2 y * +", func("-2x^2 + !2y", &options).unwrap().as_string());
+        assert_eq!("0.5 xy * y 1 + f +", func(".5xy + f(y+1)", &options).unwrap().as_string());
+        assert_eq!("2000 x * 2 e * -", func("2e3x - 2e", &options).unwrap().as_string());
+        // Off by default
+        assert_eq!("2x 3 +", func("2x+3", &ParseOptions::new()).unwrap().as_string());
+    }
+
+    pub fn identifiers(func: fn(&str) -> RpnReturn) {
+        assert_eq!("flow_rate2 Vmax * θ +", func("flow_rate2 * Vmax + θ").unwrap().as_string());
+        assert_eq!("_x X max 2 ^", func("max(_x, X)^2").unwrap().as_string());
+    }
+
+    pub fn number_literals(func: fn(&str) -> RpnReturn) {
+        assert_eq!("0.0015 x * 2 +", func("1.5e-3*x + 2").unwrap().as_string());
+        assert_eq!("31 10 + 15 -", func("0x1F + 0b1010 - 0o17").unwrap().as_string());
+        assert_eq!("1000000 0.5 20 ~ ^ /", func("1_000_000 / .5 ^ -2E+1").unwrap().as_string());
+        assert_eq!("e 10 - -0.025 *", func("(e - 1e1) * !2.5e-2").unwrap().as_string());
+        assert!(matches!(func("1.2.3 + 4"), Err(RpnError::NumberParse { .. })));
+        // A space ends a number, so the e after it is a name
+        assert_eq!("1e 1 +", func("1e + 1").unwrap().as_string());
+    }
+
+    pub fn custom_operators(func: fn(&str, &ParseOptions) -> RpnReturn) {
+        let mut operators = OperatorTable::new();
+        operators.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1])).unwrap()
+            .add("&", Operator::new(4, Associativity::Right), |args| args[0].min(args[1])).unwrap()
+            .add("√", Operator::prefix(1), |args| args[0].sqrt()).unwrap();
+        let mut options = ParseOptions::new();
+        options.set_operators(operators);
+        assert_eq!("a b | c *", func("a | b * c", &options).unwrap().as_string());
+        assert_eq!("1 2 + 3 4 & &", func("1 + 2 & 3 & 4", &options).unwrap().as_string());
+        assert_eq!("x 2 ^ √ y |", func("√x^2 | y", &options).unwrap().as_string());
+        // Unknown without the table
+        assert!(func("a | b", &ParseOptions::new()).is_err());
+    }
+
+    pub fn multi_char_operators(func: fn(&str, &ParseOptions) -> RpnReturn) {
+        assert_eq!("2 3 2 ^ ^", func("2**3**2", &ParseOptions::new()).unwrap().as_string());
+        assert_eq!("7 2 2 ^ // x *", func("7 // 2 ** 2 * x", &ParseOptions::new()).unwrap().as_string());
+        let mut operators = OperatorTable::new();
+        operators.add("<<", Operator::new(4, Associativity::Left), |args| args[0] * 2f64.powf(args[1])).unwrap()
+            .add("<", Operator::new(5, Associativity::Left), |args| f64::from(args[0] < args[1])).unwrap()
+            .add("<=", Operator::new(5, Associativity::Left), |args| f64::from(args[0] <= args[1])).unwrap();
+        let mut options = ParseOptions::new();
+        options.set_operators(operators);
+        assert_eq!("1 2 3 + <<", func("1 << 2 + 3", &options).unwrap().as_string());
+        assert_eq!("a b <= c < d 1 << <=", func("a <= b < c <= d << 1", &options).unwrap().as_string());
+    }
+
+    pub fn comparisons(func: fn(&str) -> RpnReturn) {
+        assert_eq!("pressure 100 < temp 0 >= and", func("pressure < 100 and temp >= 0").unwrap().as_string());
+        assert_eq!("a b + c 2 * <= x 1 == y -1 != or and", func("a + b <= c * 2 and (x == 1 or y != !1)").unwrap().as_string());
+        assert_eq!("a b > not c d and or", func("not a > b or c and d").unwrap().as_string());
+        assert_eq!("x ~ 0 < true ==", func("-x<0 == true").unwrap().as_string());
+        // Word operators are whole words, spaces or not
+        assert_eq!("android order and", func("android and order").unwrap().as_string());
+        assert_eq!("x y or not", func("not(x or y)").unwrap().as_string());
+    }
+
+    pub fn conditionals(func: fn(&str) -> RpnReturn) {
+        assert_eq!("x 0 > ?2 x :2 x ~", func("x > 0 ? x : -x").unwrap().as_string());
+        assert_eq!("x 0 > ?2 x :2 x ~", func("if(x > 0, x, -x)").unwrap().as_string());
+        assert_eq!("a ?2 b :5 c ?2 d :1 e", func("a ? b : c ? d : e").unwrap().as_string());
+        assert_eq!("a ?6 b ?2 c :1 d :1 e", func("a ? b ? c : d : e").unwrap().as_string());
+        assert_eq!("a ?2 b :1 c 1 +", func("(a ? b : c) + 1").unwrap().as_string());
+        assert_eq!("a b < ?2 b :1 a 2 max 3 *", func("max(a < b ? b : a, 2) * 3").unwrap().as_string());
+        assert_eq!("1 x ?2 y :1 z 2 * +", func("1 + if(x, y, z) * 2").unwrap().as_string());
+        assert!(matches!(func("if(a, b)"), Err(RpnError::ArityMismatch { expected: 3, found: 2, .. })));
+        assert!(matches!(func("a ? b"), Err(RpnError::DanglingOperator { op, .. }) if op == "?"));
+        // The jumps point at the `?` and `:`, or at the commas of `if`
+        let jump_spans = |input: &str| {
+            let rpn = func(input).unwrap();
+            rpn.tokens().iter()
+                .filter(|token| matches!(token.value, MathValue::JumpUnless(_) | MathValue::Jump(_)))
+                .map(|token| token.span)
+                .collect::<Vec<Span>>()
+        };
+        assert_eq!(vec![Span::new(2, 3), Span::new(6, 7)], jump_spans("a ? b : c"));
+        assert_eq!(vec![Span::new(4, 5), Span::new(7, 8)], jump_spans("if(a, b, c)"));
+    }
+
+    pub fn associativity(func: fn(&str) -> RpnReturn) {
+        assert_eq!("2 3 2 ^ ^", func("2^3^2").unwrap().as_string());
+        assert_eq!("8 4 - 2 - 1 -", func("8-4-2-1").unwrap().as_string());
+        assert_eq!("8 4 / 2 * 1 2 3 ^ ^ /", func("8/4*2/1^2^3").unwrap().as_string());
     }        
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+/// Reads the number literal at the start of the chars, which ends at any whitespace.
+/// Accepted forms are decimals (`12`, `1.5`, `.5`, `5.`),
+/// with an exponent (`1.5e-3`, `6.02E23`), and hex, binary or octal integers
+/// (`0x1F`, `0b1010`, `0o17`). Digits may be grouped by single underscores (`1_000_000`).
+///
+/// Returns the length in chars and the value of the literal, None if no number starts there,
+/// or the length of the malformed literal, such as `1.2.3`, `0b102`, `1__0`, `.e5` or `00x1`.
+/// A decimal literal ending at a name char is still a literal, so `2x` reads as `2` followed by `x`.
+/// The same goes for an `e` without digits after it, so `2e` is `2` followed by `e`, but not for
+/// a second exponent as in `1e5e5`.
+pub(crate) fn lex(chars: &[char]) -> Option<Result<(usize, f64), usize>> {
+    let at = |position: usize| chars.get(position).copied();
+    // A point is only ever part of a number, so `.e5` is a broken one rather than a name
+    if !at(0).is_some_and(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let radix = match (at(0), at(1).map(|c| c.to_ascii_lowercase())) {
+        (Some('0'), Some('x')) => 16,
+        (Some('0'), Some('b')) => 2,
+        (Some('0'), Some('o')) => 8,
+        _ => 10,
+    };
+    let mut end;
+    let value = if radix != 10 {
+        end = digits(chars, 2, radix);
+        // The prefix needs digits after it, and no name can follow them as it would read as more digits
+        if end == 2 || at(end).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return Some(Err(malformed(chars, end)));
+        }
+        let text: String = chars[2..end].iter().filter(|c| **c != '_').collect();
+        u128::from_str_radix(&text, radix).ok().map(|value| value as f64)
+    } else {
+        end = digits(chars, 0, 10);
+        // Extra zeros before a radix prefix, as in 00x1, rather than 00 followed by x1
+        let radix_prefix = matches!(at(end).map(|c| c.to_ascii_lowercase()), Some('x' | 'b' | 'o'));
+        if end > 1 && radix_prefix && chars[..end].iter().all(|c| *c == '0') {
+            return Some(Err(malformed(chars, end)));
+        }
+        if at(end) == Some('.') {
+            end = digits(chars, end + 1, 10);
+        }
+        let sign = usize::from(matches!(at(end + 1), Some('+' | '-')));
+        let exponent = matches!(at(end), Some('e' | 'E')) && at(end + 1 + sign).is_some_and(|c| c.is_ascii_digit());
+        if exponent {
+            end = digits(chars, end + 1 + sign, 10);
+            // Only one exponent, so 1e5e5 is broken
+            if matches!(at(end), Some('e' | 'E')) {
+                return Some(Err(malformed(chars, end)));
+            }
+        }
+        let text: String = chars[..end].iter().filter(|c| **c != '_').collect();
+        text.parse::<f64>().ok()
+    };
+    // Another point, digit or underscore straight after means the literal is broken, as in 1.2.3 or 1_
+    let broken = matches!(at(end), Some(c) if c.is_ascii_digit() || matches!(c, '.' | '_'));
+    match value {
+        Some(value) if !broken => Some(Ok((end, value))),
+        // Such as a point without digits, as in .e5
+        _ => Some(Err(malformed(chars, end))),
+    }
+}
+
+/// Position after the rest of a malformed literal, which runs on through any
+/// letters, digits, points and underscores
+fn malformed(chars: &[char], mut end: usize) -> usize {
+    while chars.get(end).is_some_and(|c| c.is_alphanumeric() || *c == '.' || *c == '_') {
+        end += 1;
+    }
+    end
+}
+
+/// Position after the digits of the radix starting at the position,
+/// which may be separated by single underscores
+fn digits(chars: &[char], mut position: usize, radix: u32) -> usize {
+    let is_digit = |position: usize| chars.get(position).is_some_and(|c| c.is_digit(radix));
+    loop {
+        match chars.get(position) {
+            Some(_) if is_digit(position) => position += 1,
+            Some('_') if position > 0 && is_digit(position - 1) && is_digit(position + 1) => position += 1,
+            _ => return position,
+        }
+    }
+}
+
+#[cfg(test)]
+mod number_tests {
+    use super::*;
+
+    fn read(text: &str) -> Option<Result<(usize, f64), usize>> {
+        lex(&text.chars().collect::<Vec<char>>())
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Some(Ok((6, 1.5e-3))), read("1.5e-3"));
+        assert_eq!(Some(Ok((7, 6.02e23))), read("6.02E23"));
+        assert_eq!(Some(Ok((5, 1e5))), read("1e+05"));
+        assert_eq!(Some(Ok((4, 31.0))), read("0x1F"));
+        assert_eq!(Some(Ok((6, 10.0))), read("0b1010"));
+        assert_eq!(Some(Ok((4, 15.0))), read("0o17"));
+        assert_eq!(Some(Ok((9, 1_000_000.0))), read("1_000_000"));
+        assert_eq!(Some(Ok((2, 0.5))), read(".5"));
+        assert_eq!(Some(Ok((2, 5.0))), read("5."));
+    }
+    #[test]
+    fn stops_at_names() {
+        assert_eq!(Some(Ok((3, 2.5))), read("2.5x"));
+        assert_eq!(Some(Ok((3, 2000.0))), read("2e3x"));
+        assert_eq!(Some(Ok((2, 12.0))), read("12+3"));
+        assert_eq!(None, read("x1"));
+    }
+    #[test]
+    fn malformed() {
+        assert_eq!(Some(Err(5)), read("1.2.3"));
+        assert_eq!(Some(Err(4)), read("1..2+3"));
+        assert_eq!(Some(Err(5)), read("0b102"));
+        assert_eq!(Some(Err(5)), read("1e5.2"));
+    }
+    #[test]
+    fn radix_needs_digits() {
+        assert_eq!(Some(Err(2)), read("0x"));
+        assert_eq!(Some(Err(3)), read("0xg"));
+        assert_eq!(Some(Err(3)), read("0b2"));
+        assert_eq!(Some(Err(4)), read("0x1G"));
+        assert_eq!(Some(Err(4)), read("0o_7"));
+    }
+    #[test]
+    fn underscores_between_digits() {
+        assert_eq!(Some(Err(2)), read("1_"));
+        assert_eq!(Some(Err(4)), read("1__0"));
+        assert_eq!(Some(Err(4)), read("1_.5"));
+        assert_eq!(Some(Err(5)), read("0x1F_"));
+    }
+    #[test]
+    fn exponent_needs_digits() {
+        assert_eq!(Some(Ok((1, 2.0))), read("2e"));
+        assert_eq!(Some(Ok((3, 1.5))), read("1.5e"));
+        assert_eq!(Some(Ok((1, 2.0))), read("2e-x"));
+        assert_eq!(Some(Err(5)), read("1e5e5"));
+        assert_eq!(Some(Err(5)), read("1e5ex"));
+    }
+    #[test]
+    fn points_and_zeros() {
+        assert_eq!(Some(Err(3)), read(".e5"));
+        assert_eq!(Some(Err(2)), read(".x"));
+        assert_eq!(Some(Err(1)), read(". 5"));
+        assert_eq!(Some(Err(4)), read("00x1"));
+        assert_eq!(Some(Ok((3, 7.0))), read("007"));
+    }
+}
@@ -0,0 +1,228 @@
+use super::*;
+use std::sync::Arc;
+
+/// Calculates an operator from its operands, in the order they were written
+pub type Evaluation = Arc<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+
+/// The operators known to validation, both converters and the evaluator, each held
+/// under the symbol it is written as in rpn, such as `+`, `//` or `and`.
+/// `OperatorTable::new()` holds the built-in arithmetic, comparison and boolean
+/// operators, which the evaluator calculates itself so % and // follow the context's
+/// modulo mode and comparisons give `Value::Bool`. Any other operator is added with
+/// the closure that calculates it.
+#[derive(Clone)]
+pub struct OperatorTable {
+    operators: HashMap<String, (Operator, Option<Evaluation>)>,
+    /// How each operator can be written with the operator it stands for,
+    /// longest first so input is matched greedily
+    symbols: Vec<(String, String)>,
+}
+
+lazy_static! {
+    /// Shared by every default ParseOptions and Context rather than rebuilt for each
+    pub(crate) static ref default_operators: Arc<OperatorTable> = Arc::new(OperatorTable::new());
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        OperatorTable::new()
+    }
+}
+
+impl fmt::Debug for OperatorTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut operators: Vec<(String, &Operator)> = self.operators.iter()
+            .map(|(op, (operator, _))| (op.to_string(), operator))
+            .collect();
+        operators.sort_by(|(a, _), (b, _)| a.cmp(b));
+        f.debug_map().entries(operators).finish()
+    }
+}
+
+impl OperatorTable {
+    pub fn new() -> OperatorTable {
+        let builtin = [
+            ("^", Operator::new(0, Associativity::Right)),
+            (NEGATE, Operator::prefix(1)),
+            ("*", Operator::new(2, Associativity::Left)),
+            ("/", Operator::new(2, Associativity::Left)),
+            (INT_DIV, Operator::new(2, Associativity::Left)),
+            ("%", Operator::new(2, Associativity::Left)),
+            ("+", Operator::new(3, Associativity::Left)),
+            ("-", Operator::new(3, Associativity::Left)),
+            ("<", Operator::new(4, Associativity::Left)),
+            (">", Operator::new(4, Associativity::Left)),
+            (LESS_EQUAL, Operator::new(4, Associativity::Left)),
+            (GREATER_EQUAL, Operator::new(4, Associativity::Left)),
+            (EQUAL, Operator::new(4, Associativity::Left)),
+            (NOT_EQUAL, Operator::new(4, Associativity::Left)),
+            (NOT, Operator::prefix(5)),
+            (AND, Operator::new(6, Associativity::Left)),
+            (OR, Operator::new(7, Associativity::Left)),
+        ];
+        let mut table = OperatorTable {
+            operators: builtin.into_iter().map(|(op, operator)| (op.to_string(), (operator, None))).collect(),
+            symbols: Vec::new(),
+        };
+        for (op, _) in builtin {
+            table.add_symbol(op, op);
+        }
+        // Another way of writing a power
+        table.add_symbol("**", "^");
+        table
+    }
+
+    /// Adds an operator, or replaces the one with the same symbol. Binary operators sit
+    /// between their operands, prefix operators before theirs. Symbols may be more than
+    /// one char, such as `<<`, and are matched longest first, or a word such as `xor`,
+    /// matched only as a whole word. Symbols mixing letters with other chars, or using chars
+    /// that are part of values, brackets or commas, can't be operators and give `RpnError::InvalidOperator`.
+    ///
+    /// Parallel resistance, binding as tightly as `*`:
+    /// `table.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1]))`
+    pub fn add<F>(&mut self, symbol: &str, operator: Operator, evaluate: F) -> Result<&mut OperatorTable, RpnError>
+    where F: Fn(&[f64]) -> f64 + Send + Sync + 'static {
+        // Negation is written '-' in input, so its rpn symbol can't be part of another
+        let reserved = |c: char| is_name_char(c) || c.is_ascii_digit() || c.is_whitespace() ||
+            "(),.!¬?:".contains(c) || NEGATE.contains(c);
+        let invalid = symbol.chars().any(reserved) && !is_identifier(symbol);
+        if invalid || symbol.is_empty() {
+            return Err(RpnError::InvalidOperator { op: symbol.to_string(), span: Span::default() });
+        }
+        self.operators.insert(symbol.to_string(), (operator, Some(Arc::new(evaluate))));
+        self.add_symbol(symbol, symbol);
+        Ok(self)
+    }
+
+    fn add_symbol(&mut self, symbol: &str, op: &str) {
+        self.symbols.retain(|(known, _)| known != symbol);
+        self.symbols.push((symbol.to_string(), op.to_string()));
+        self.symbols.sort_by_key(|(symbol, _)| std::cmp::Reverse(symbol.len()));
+    }
+
+    /// The operator written as the symbol
+    pub fn get(&self, symbol: &str) -> Option<Operator> {
+        self.symbols.iter()
+            .find(|(known, _)| known == symbol)
+            .and_then(|(_, op)| self.operator(op))
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.get(symbol).is_some()
+    }
+
+    /// The operator held under its rpn symbol, such as `^` but not `**`
+    pub(crate) fn operator(&self, op: &str) -> Option<Operator> {
+        self.operators.get(op).map(|(operator, _)| *operator)
+    }
+
+    pub(crate) fn has_operator(&self, op: &str) -> bool {
+        self.operators.contains_key(op)
+    }
+
+    /// The longest operator the text starts with, as its rpn symbol, and its length in bytes
+    pub(crate) fn lex(&self, text: &str) -> Option<(&str, usize)> {
+        self.symbols.iter()
+            .find(|(symbol, _)| text.starts_with(symbol.as_str()))
+            .map(|(symbol, op)| (op.as_str(), symbol.len()))
+    }
+
+    /// The operator written as the word, such as `and`
+    pub(crate) fn word(&self, word: &str) -> Option<&str> {
+        self.symbols.iter()
+            .find(|(symbol, _)| symbol == word && is_identifier(symbol))
+            .map(|(_, op)| op.as_str())
+    }
+
+    /// True if the operator is calculated by a closure given to `add`
+    pub(crate) fn is_custom(&self, op: &str) -> bool {
+        self.operators.get(op).is_some_and(|(_, evaluation)| evaluation.is_some())
+    }
+
+    /// Every way an operator can be written in input, tightest binding first
+    pub(crate) fn written_symbols(&self) -> Vec<&str> {
+        let mut written: Vec<(u8, &str)> = self.symbols.iter()
+            // Negation is written '-'
+            .filter(|(symbol, _)| symbol != NEGATE)
+            .filter_map(|(symbol, op)| Some((self.operator(op)?.precedence, symbol.as_str())))
+            .collect();
+        written.sort_by_key(|(precedence, symbol)| (*precedence, symbol.len(), *symbol));
+        written.into_iter().map(|(_, symbol)| symbol).collect()
+    }
+
+    /// True if the char is used to write any operator, even as part of a longer one
+    pub(crate) fn writes_with(&self, c: char) -> bool {
+        self.symbols.iter().any(|(symbol, _)| symbol.contains(c))
+    }
+
+    /// Applies the operator to its operands. None if the operator is unknown
+    /// or given the wrong number of operands
+    pub(crate) fn calculate(&self, op: &str, args: &[f64], modulo_mode: ModuloMode) -> Option<f64> {
+        let (operator, evaluation) = self.operators.get(op)?;
+        if operator.arity != args.len() {
+            return None;
+        }
+        match (evaluation, args) {
+            (Some(evaluation), _) => Some(evaluation(args)),
+            (None, [a]) => evaluate::calculate_unary(*a, op),
+            (None, [a, b]) => evaluate::calculate(*a, *b, op, modulo_mode),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod operators_tests {
+    use super::*;
+
+    #[test]
+    fn builtin_operators() {
+        let table = OperatorTable::new();
+        assert_eq!(Some(Operator::new(0, Associativity::Right)), table.get("^"));
+        assert_eq!(table.get("^"), table.get("**"));
+        assert_eq!(Some(1), table.get("~").map(|operator| operator.arity));
+        assert!(table.contains("//"));
+        assert!(!table.contains("|"));
+        assert_eq!(Some(-1.0), table.calculate("%", &[-7.0, 3.0], ModuloMode::Truncated));
+        assert_eq!(Some(2.0), table.calculate("%", &[-7.0, 3.0], ModuloMode::Floored));
+        assert_eq!(None, table.calculate("+", &[1.0], ModuloMode::Truncated));
+        assert_eq!(Some(Operator::new(4, Associativity::Left)), table.get("<="));
+        assert_eq!(Some(Operator::prefix(5)), table.get("not"));
+        assert_eq!(Some(6), table.get("and").map(|operator| operator.precedence));
+    }
+    #[test]
+    fn word_operators() {
+        let mut table = OperatorTable::new();
+        table.add("xor", Operator::new(7, Associativity::Left), |args| f64::from((args[0] != 0.0) != (args[1] != 0.0))).unwrap();
+        assert_eq!(Some("xor"), table.word("xor"));
+        assert_eq!(Some(AND), table.word("and"));
+        assert_eq!(None, table.word("android"));
+        assert_eq!(None, table.word("<="));
+    }
+    #[test]
+    fn custom_operators() {
+        let mut table = OperatorTable::new();
+        table.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1])).unwrap()
+            .add("+", Operator::new(3, Associativity::Left), |args| args[0] + args[1] + 1.0).unwrap();
+        assert_eq!(Some(2.0), table.calculate("|", &[3.0, 6.0], ModuloMode::Truncated));
+        assert_eq!(Some(4.0), table.calculate("+", &[1.0, 2.0], ModuloMode::Truncated));
+        assert!(table.add("x+", Operator::prefix(1), |args| args[0]).is_err());
+        assert!(table.add("-~", Operator::prefix(1), |args| args[0]).is_err());
+        assert_eq!(Some(Operator::prefix(1)), table.add("≤", Operator::prefix(1), |args| args[0]).unwrap().get("≤"));
+        assert!(table.add("?:", Operator::prefix(1), |args| args[0]).is_err());
+        assert!(table.add("<(", Operator::prefix(1), |args| args[0]).is_err());
+        assert!(table.add("", Operator::prefix(1), |args| args[0]).is_err());
+    }
+    #[test]
+    fn longest_match() {
+        let mut table = OperatorTable::new();
+        table.add("<<", Operator::new(1, Associativity::Left), |args| args[0] * 2f64.powf(args[1])).unwrap();
+        assert_eq!(Some(("<<", 2)), table.lex("<< 3"));
+        assert_eq!(Some(("^", 2)), table.lex("**2"));
+        assert_eq!(Some(("*", 1)), table.lex("* *2"));
+        assert_eq!(Some((INT_DIV, 2)), table.lex("///"));
+        assert_eq!(Some(("<", 1)), table.lex("< 3"));
+        assert_eq!(Some((NOT_EQUAL, 2)), table.lex("!=!3"));
+        assert!(table.writes_with('<'));
+    }
+}
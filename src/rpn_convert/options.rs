@@ -0,0 +1,49 @@
+use super::*;
+use std::sync::Arc;
+
+/// Choices about how infix input is read, shared by validation and both converters.
+/// The default reads input exactly as the crate always has.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    implicit_multiplication: bool,
+    operators: Arc<OperatorTable>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::new()
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions {
+            implicit_multiplication: false,
+            operators: Arc::clone(&operators::default_operators),
+        }
+    }
+
+    /// Reads values written side by side as multiplied: `2x`, `3(x+1)`, `(a+b)(a-b)`, `2pi`.
+    /// A number is split from the name straight after it, but the letters of a name stay
+    /// together, so `2xy` is `2 * xy`. A name straight before a bracket is still a call,
+    /// unless it is a constant such as `pi`.
+    pub fn set_implicit_multiplication(&mut self, on: bool) -> &mut ParseOptions {
+        self.implicit_multiplication = on;
+        self
+    }
+
+    pub fn implicit_multiplication(&self) -> bool {
+        self.implicit_multiplication
+    }
+
+    /// Reads the input with these operators instead of the built-in ones.
+    /// The context it is solved with needs the same table to calculate them.
+    pub fn set_operators(&mut self, operators: OperatorTable) -> &mut ParseOptions {
+        self.operators = Arc::new(operators);
+        self
+    }
+
+    pub fn operators(&self) -> &OperatorTable {
+        &self.operators
+    }
+}
@@ -0,0 +1,162 @@
+use super::*;
+
+/// Reads whitespace separated rpn, such as the output of `get_rpn_yard`, into a stack.
+/// Numbers are written as in infix and may be negative literals ("-5", "-1.5e-3"),
+/// functions take their arity from the built-in table, or from their definition when read
+/// with a context, `true` and `false` are booleans,
+/// `?n` and `:n` are the jumps of a conditional and anything else made of letters
+/// and digits is a variable.
+/// Operand counts are only checked once the stack is evaluated.
+pub fn parse(input: &str) -> Result<Stack, RpnError> {
+    parse_with(input, &operators::default_operators)
+}
+
+/// Reads rpn using the operators in the table
+pub fn parse_with(input: &str, operators: &OperatorTable) -> Result<Stack, RpnError> {
+    read_words(input, operators, None)
+}
+
+/// Reads rpn using the operators of the context, calling the functions defined in it by name
+pub fn parse_with_context(input: &str, context: &Context) -> Result<Stack, RpnError> {
+    read_words(input, context.operators(), Some(context))
+}
+
+fn read_words(input: &str, operators: &OperatorTable, context: Option<&Context>) -> Result<Stack, RpnError> {
+    let mut stack = Stack::new();
+    for (start, word) in words(input) {
+        let span = Span::new(start, start + word.len());
+        stack.push(Token::new(parse_word(word, span, operators, context)?, span));
+    }
+    Ok(stack)
+}
+
+/// Splits the input on whitespace, keeping the byte offset of each word
+pub(crate) fn words(input: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    for (index, ch) in input.char_indices() {
+        match (ch.is_whitespace(), start) {
+            (true, Some(from)) => {
+                words.push((from, &input[from..index]));
+                start = None;
+            },
+            (false, None) => start = Some(index),
+            _ => (),
+        }
+    }
+    if let Some(from) = start {
+        words.push((from, &input[from..]));
+    }
+    words
+}
+
+/// Reads a single value, operator or function name.
+/// Names of functions defined in the context take the arity of their definition.
+fn parse_word(word: &str, span: Span, operators: &OperatorTable, context: Option<&Context>) -> Result<MathValue, RpnError> {
+    if let Some((op, _)) = operators.lex(word).filter(|(_, length)| *length == word.len()) {
+        return Ok(MathValue::Op(op.to_string()));
+    }
+    if let Some(function) = functions::builtin(word) {
+        return Ok(MathValue::Func(word.to_string(), function.arity));
+    }
+    if word == functions::CONDITIONAL {
+        return Ok(MathValue::Func(word.to_string(), 3));
+    }
+    if let Some(function) = context.and_then(|context| context.function(word)) {
+        return Ok(MathValue::Func(word.to_string(), function.params().len()));
+    }
+    // The jumps of a conditional, `?n` and `:n`
+    let jump = |prefix: char| word.strip_prefix(prefix).and_then(|skip| skip.parse::<usize>().ok());
+    if let Some(skip) = jump('?') {
+        return Ok(MathValue::JumpUnless(skip));
+    }
+    if let Some(skip) = jump(':') {
+        return Ok(MathValue::Jump(skip));
+    }
+    if context::builtin_constant(word).is_some() {
+        return Ok(MathValue::Const(word.to_string()));
+    }
+    if let Ok(value) = word.parse::<bool>() {
+        return Ok(MathValue::Bool(value));
+    }
+
+    let unsigned = word.trim_start_matches('-');
+    let chars: Vec<char> = unsigned.chars().collect();
+    match number::lex(&chars) {
+        Some(Ok((length, num))) if length == chars.len() && word.len() - unsigned.len() <= 1 => {
+            return Ok(MathValue::Num(if unsigned.len() < word.len() { -num } else { num }));
+        },
+        Some(Err(_)) => return Err(RpnError::NumberParse { text: word.to_string(), span }),
+        _ => (),
+    }
+    // Variables, and the glued values of algebraic rpn such as "-4.3a"
+    let bad_char = unsigned.char_indices().find(|(_, c)| !(is_name_char(*c) || c.is_ascii_digit() || *c == '.'));
+    match bad_char {
+        None if unsigned.chars().any(is_name_char) => Ok(MathValue::Alge(word.to_string())),
+        None if !unsigned.is_empty() => Err(RpnError::NumberParse { text: word.to_string(), span }),
+        Some(_) if word.chars().count() == 1 => Err(RpnError::InvalidOperator { op: word.to_string(), span }),
+        Some((index, ch)) => {
+            let start = span.start + word.len() - unsigned.len() + index;
+            Err(RpnError::UnknownChar { ch, span: Span::of_char(start, ch) })
+        },
+        None => Err(RpnError::InvalidOperator { op: word.to_string(), span }),
+    }
+}
+
+#[cfg(test)]
+mod postfix_tests {
+    use super::*;
+
+    fn solve(input: &str) -> Result<f64, RpnError> {
+        evaluate::evaluate(&parse(input)?, &Context::new())
+    }
+
+    #[test]
+    fn evaluates_rpn() {
+        assert_eq!(Ok(14.0), solve("3 4 + 2 *"));
+        assert_eq!(Ok(-7.0), solve("  -5 2 ~ +\t"));
+        assert_eq!(Ok(1.0), solve("7 3 // 5 2 % -"));
+        assert_eq!(Ok(4.0), solve("3 4 max 2 1 atan2 0 * +"));
+        assert_eq!(Ok(std::f64::consts::TAU), solve("2 pi *"));
+        assert_eq!(Ok(31.5), solve("0x1F 1_000 -2e3 / -"));
+        assert_eq!(Ok(512.0), solve("2 3 2 ** **"));
+        assert_eq!(Ok(6.0), solve("1 0 < ?2 5 :1 6"));
+    }
+    #[test]
+    fn calls_defined_functions() {
+        let mut context = Context::new();
+        context.define("f(x, y) = x^2 + y").unwrap();
+        let stack = parse_with_context("3 4 f", &context).unwrap();
+        assert!(matches!(stack.tokens()[2].value, MathValue::Func(ref name, 2) if name == "f"));
+        assert_eq!(Ok(13.0), evaluate::evaluate(&stack, &context));
+        // Without the context the name is a variable
+        assert!(matches!(parse("3 4 f").unwrap().tokens()[2].value, MathValue::Alge(_)));
+    }
+    #[test]
+    fn reads_every_kind_of_token() {
+        let stack = parse("x -2.5 ~ sin pi // -y").unwrap();
+        let values: Vec<&MathValue> = stack.iter().collect();
+        assert_eq!(7, values.len());
+        assert!(matches!(values[0], MathValue::Alge(x) if x == "x"));
+        assert!(matches!(values[1], MathValue::Num(n) if *n == -2.5));
+        assert!(values[2].is_op(NEGATE));
+        assert!(matches!(values[3], MathValue::Func(name, 1) if name == "sin"));
+        assert!(matches!(values[4], MathValue::Const(name) if name == "pi"));
+        assert!(values[5].is_op(INT_DIV));
+        assert!(matches!(values[6], MathValue::Alge(y) if y == "-y"));
+        assert_eq!(Span::new(2, 6), stack.tokens()[1].span);
+        assert_eq!("x -2.5 ~ sin pi // -y", stack.as_string());
+    }
+    #[test]
+    fn stack_errors_point_at_tokens() {
+        assert_eq!(Err(RpnError::StackUnderflow { op: "+".to_string(), span: Span::new(6, 7) }), solve("3 4 + +"));
+        assert_eq!(Err(RpnError::StackUnderflow { op: "max".to_string(), span: Span::new(2, 5) }), solve("1 max"));
+        assert_eq!(Err(RpnError::LeftoverOperands { span: Span::new(0, 7) }), solve("3 4 5 +"));
+    }
+    #[test]
+    fn rejects_bad_words() {
+        assert_eq!(RpnError::InvalidOperator { op: "=".to_string(), span: Span::new(4, 5) }, parse("1 2 =").unwrap_err());
+        assert_eq!(RpnError::NumberParse { text: "1.2.3".to_string(), span: Span::new(0, 5) }, parse("1.2.3 2 +").unwrap_err());
+        assert_eq!(RpnError::UnknownChar { ch: '@', span: Span::new(3, 4) }, parse("1 a@b +").unwrap_err());
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+
+/// Reads whitespace separated prefix (polish notation), such as "* + 31 321 + 32 54",
+/// into a stack in the order it was written. Words are read as in `postfix::parse`.
+pub fn parse(input: &str) -> Result<Stack, RpnError> {
+    parse_with(input, &operators::default_operators)
+}
+
+/// Reads prefix using the operators in the table
+pub fn parse_with(input: &str, operators: &OperatorTable) -> Result<Stack, RpnError> {
+    postfix::parse_with(input, operators)
+}
+
+/// Reads prefix using the operators of the context, calling the functions defined in it by name
+pub fn parse_with_context(input: &str, context: &Context) -> Result<Stack, RpnError> {
+    postfix::parse_with_context(input, context)
+}
+
+/// Reorders a prefix stack into rpn so it can be evaluated or printed as postfix.
+/// Checks the operands from the last token back, so each operator finds its operands already read,
+/// then writes the rpn in one pass from the first token.
+/// A conditional, `if c a b`, becomes the jumps of `c ? a : b`.
+pub fn convert_pre_to_post_fix(prefix: &Stack) -> Result<Stack, RpnError> {
+    convert_pre_to_post_fix_with(prefix, &operators::default_operators)
+}
+
+/// Reorders a prefix stack into rpn, taking the arity of each operator from the table
+pub fn convert_pre_to_post_fix_with(prefix: &Stack, operators: &OperatorTable) -> Result<Stack, RpnError> {
+    let tokens = prefix.tokens();
+    let arity = |token: &Token| match &token.value {
+        MathValue::Op(op) => operators.operator(op).map_or(2, |operator| operator.arity),
+        MathValue::Func(_, arity) => *arity,
+        _ => 0,
+    };
+    // The span of each finished sub expression
+    let mut operands: Vec<Span> = Vec::new();
+    for token in tokens.iter().rev() {
+        let (arity, op) = match &token.value {
+            MathValue::Op(name) | MathValue::Func(name, _) => (arity(token), name),
+            _ => {
+                operands.push(token.span);
+                continue;
+            },
+        };
+        if operands.len() < arity {
+            return Err(RpnError::StackUnderflow { op: op.to_string(), span: token.span });
+        }
+        let span = operands.split_off(operands.len() - arity).into_iter().fold(token.span, Span::join);
+        operands.push(span);
+    }
+    match operands.len() {
+        0 => return Err(RpnError::TooShort { span: Span::default() }),
+        1 => (),
+        _ => return Err(RpnError::LeftoverOperands { span: operands.into_iter().reduce(Span::join).unwrap_or_default() }),
+    }
+
+    let mut rpn = Stack::new();
+    // Each operator still reading its operands, with how many it has read
+    // and where the last jump was written if it is a conditional
+    let mut pending: Vec<(&Token, usize, usize)> = Vec::new();
+    for token in tokens {
+        if arity(token) > 0 {
+            pending.push((token, 0, 0));
+            continue;
+        }
+        rpn.push(token.clone());
+        // Every operator whose last operand this was is finished, which is an operand of the one before
+        while let Some((operator, read, jump)) = pending.last_mut() {
+            *read += 1;
+            let conditional = matches!(&operator.value, MathValue::Func(name, 3) if name == functions::CONDITIONAL);
+            // Conditionals jump around each branch rather than following them,
+            // each jump filled in once the branch it skips is written
+            match (conditional, *read) {
+                (true, 1) => {
+                    *jump = rpn.len();
+                    rpn.push(Token::new(MathValue::JumpUnless(0), operator.span));
+                },
+                (true, 2) => {
+                    rpn.elements[*jump].value = MathValue::JumpUnless(rpn.len() - *jump);
+                    *jump = rpn.len();
+                    rpn.push(Token::new(MathValue::Jump(0), operator.span));
+                },
+                (true, _) => rpn.elements[*jump].value = MathValue::Jump(rpn.len() - *jump - 1),
+                (false, read) if read == arity(operator) => rpn.push(operator.clone()),
+                _ => (),
+            }
+            if *read < arity(operator) {
+                break;
+            }
+            pending.pop();
+        }
+    }
+    Ok(rpn)
+}
+
+#[cfg(test)]
+mod prefix_tests {
+    use super::*;
+
+    fn to_rpn(input: &str) -> Result<String, RpnError> {
+        Ok(convert_pre_to_post_fix(&parse(input)?)?.as_string())
+    }
+
+    #[test]
+    fn reorders_to_rpn() {
+        assert_eq!(Ok("31 321 + 32 54 + *".to_string()), to_rpn("* + 31 321 + 32 54"));
+        assert_eq!(Ok("8 4 - 2 -".to_string()), to_rpn("- - 8 4 2"));
+        assert_eq!(Ok("x 2 y ~ * max ~".to_string()), to_rpn("~ max x * 2 ~ y"));
+        assert_eq!(Ok("-5 3 //".to_string()), to_rpn("// -5 3"));
+        assert_eq!(Ok("x 0 > ?2 x :2 x ~".to_string()), to_rpn("if > x 0 x ~ x"));
+    }
+    #[test]
+    fn same_as_the_tree() {
+        for input in ["(31 + 321)*(32+54)", "2^3^2 % 5", "-(x - 1)^2 // rate", "atan2(y - 1, -x) * log(2, x)^-2",
+            "a ? b ? c : d : e", "max(a < b ? b : a, 2) * 3", "1 + if(x, y, z ? 2 : 3) * 2"] {
+            let prefix = ast_tree::convert_in_to_pre_fix(input).unwrap();
+            let rpn = ast_tree::convert_in_to_post_fix(input).unwrap();
+            assert_eq!(rpn.as_string(), convert_pre_to_post_fix(&prefix).unwrap().as_string(), "{}", input);
+        }
+    }
+    #[test]
+    fn long_chains() {
+        let sum = "+ ".repeat(9999) + &vec!["x"; 10000].join(" ");
+        assert_eq!(String::from("x") + &" x +".repeat(9999), to_rpn(&sum).unwrap());
+        let conditionals = "if a b ".repeat(5000) + "c";
+        let rpn = to_rpn(&conditionals).unwrap();
+        assert!(rpn.starts_with(&format!("a ?2 b :{} a", 4 * 4999 + 1)) && rpn.ends_with("a ?2 b :1 c"), "{}", rpn);
+    }
+    #[test]
+    fn operand_errors() {
+        assert_eq!(Err(RpnError::StackUnderflow { op: "*".to_string(), span: Span::new(0, 1) }), to_rpn("* + 1 2"));
+        assert_eq!(Err(RpnError::StackUnderflow { op: "max".to_string(), span: Span::new(0, 3) }), to_rpn("max 1"));
+        assert_eq!(Err(RpnError::LeftoverOperands { span: Span::new(0, 7) }), to_rpn("+ 1 2 3"));
+    }
+}
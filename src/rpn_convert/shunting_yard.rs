@@ -1,92 +1,192 @@
 use super::*;
 
-pub fn convert_in_to_post_fix(input: &str) -> Result<Stack, Box<dyn Error>> {
+pub fn convert_in_to_post_fix(input: &str) -> Result<Stack, RpnError> {
+    convert_in_to_post_fix_with(input, &ParseOptions::default())
+}
+
+/// Converts the input to rpn, reading it with the options.
+/// Conditionals, `c ? a : b` or `if(c, a, b)`, are written with jumps around
+/// each branch, so the rpn only evaluates the branch taken.
+pub fn convert_in_to_post_fix_with(input: &str, options: &ParseOptions) -> Result<Stack, RpnError> {
     let mut operators = Stack::new();
     let mut output = Stack::new();
+    // Length of the output when each open function call started
+    let mut calls: Vec<usize> = Vec::new();
+    // Position in the output of each jump whose length is not yet known
+    let mut jumps: Vec<usize> = Vec::new();
     
-    // Clean spaces from string
-    let input = input.replace(' ', "");
-    
-    let mut digit_tracker = false;
-    let mut number_as_string: String = String::new();
-    
-    // True for alge, false for num
-    let conversion_type_has_alge: bool = input.chars().any(|c| c.is_alphabetic());
-
-    // Loop through chars in input
-    for token in input.chars() {
-        // If digit
-        if handle_non_op_token(&token, &mut digit_tracker, &mut number_as_string) {
-            continue;
-        }
-
-        // Convert string to f64 and push it to the output
-        // Reset digit_tracker and number_as_string
-        if digit_tracker {
-            push_conversion_type(&mut output, number_as_string, conversion_type_has_alge)?;
-            digit_tracker = false;
-            number_as_string = "".to_string();
-        }
-
-        // If Operator or Bracket 
-        if let Err(e) = handle_operators(&token, &mut operators, &mut output) {
-            return Err(e);
+    // Loop through tokens in input
+    for token in Stack::tokenize(input, options)?.elements {
+        match token.value {
+            // If Operator or Bracket 
+            MathValue::Op(_) => handle_operators(token, options.operators(), &mut operators, &mut output, &mut calls, &mut jumps)?,
+            // Functions wait for their arguments, like a bracket
+            MathValue::Func(..) => operators.push(token),
+            // Values go straight to the output
+            _ => output.push(token),
         }
     }
-    if digit_tracker { 
-        push_conversion_type(&mut output, number_as_string, conversion_type_has_alge)?;
-    }    
     while let Some(ops) = operators.pop() {
-        output.push(ops);
+        // Any bracket left was never closed
+        if ops.value.is_op("(") || matches!(ops.value, MathValue::Func(..)) {
+            return Err(RpnError::MismatchedParenthesis { span: ops.span });
+        }
+        output_operator(ops, &mut output, &mut jumps)?;
     }
     Ok(output)
     
 }
 
-fn handle_operators(token: &char, operators: &mut Stack, output: &mut Stack) -> Result<(), Box<std::io::Error>> {
-    match pres_map.get(&token) {            
+fn handle_operators(token: Token, table: &OperatorTable, operators: &mut Stack, output: &mut Stack, calls: &mut Vec<usize>, jumps: &mut Vec<usize>) -> Result<(), RpnError> {
+    let MathValue::Op(symbol) = &token.value else {
+        return Ok(());
+    };
+    match table.operator(symbol) {            
         // Operators
-        Some(pres) => {
-            while let Some(top_of_stack) = operators.peak() {
-                if let MathValue::Op(op) = top_of_stack {
-                    // If bracket, set to prec value which always fails
-                    if pres_map.get(op).unwrap_or(&8) <= pres {
-                        output.push(operators.pop().unwrap());
-                    } else {
-                        break;
-                    }
+        // Prefix operators have no left operand, so nothing waiting can apply first
+        Some(operator) if operator.arity == 1 => operators.push(token),
+        Some(operator) => {
+            while let Some(MathValue::Op(op)) = operators.peak() {
+                // Brackets are not in the table so always stop the popping
+                match table.operator(op) {
+                    Some(top) if operator.yields_to(&top) => (),
+                    _ => break,
+                }
+                if let Some(top_of_stack) = operators.pop() {
+                    output.push(top_of_stack);
                 }
             }
-            operators.push(MathValue::Op(*token));                                 
+            operators.push(token);                                 
         },
         None => {
             // Brackets
-            if *token == '(' {
-                operators.push(MathValue::Op(*token));
-            } else if *token == ')' {
+            if symbol == "(" {
+                if let Some(MathValue::Func(..)) = operators.peak() {
+                    calls.push(output.len());
+                }
+                operators.push(token);
+            } else if symbol == ")" {
                 // If left bracket, discard
                 // else push to output 
-                while let Some(MathValue::Op(op)) = operators.pop() {
-                    if op == '(' {
-                        break;
+                pop_to_bracket(operators, output, jumps, RpnError::MismatchedParenthesis { span: token.span })?;
+                operators.pop();
+                // Closing a call moves the function to the output
+                if let Some(Token { value: MathValue::Func(name, commas), span }) = operators.peak_token().cloned() {
+                    operators.pop();
+                    let started_at = calls.pop().unwrap_or_default();
+                    let arity = if output.len() == started_at { 0 } else { commas + 1 };
+                    if name != functions::CONDITIONAL {
+                        output.push(Token::new(MathValue::Func(name, arity), span));
+                    } else if arity == 3 {
+                        // Already written as jumps between the arguments
+                        land_jump(output, jumps);
                     } else {
-                        output.push(MathValue::Op(op));
+                        return Err(RpnError::ArityMismatch { name, expected: 3, found: arity, span });
                     }
                 }
+            } else if symbol == "," {
+                // Finish the argument, the function counts its commas
+                let stray_comma = RpnError::DanglingOperator { op: symbol.to_string(), span: token.span };
+                pop_to_bracket(operators, output, jumps, stray_comma.clone())?;
+                match operators.elements.iter_mut().rev().nth(1) {
+                    Some(Token { value: MathValue::Func(name, commas), .. }) => {
+                        // The condition and first branch of a conditional end in a jump
+                        if name == functions::CONDITIONAL && *commas < 2 {
+                            let jump = if *commas == 0 { MathValue::JumpUnless(0) } else { MathValue::Jump(0) };
+                            start_jump(jump, token.span, output, jumps);
+                        }
+                        *commas += 1;
+                    },
+                    _ => return Err(stray_comma),
+                }
+            } else if symbol == "?" {
+                // The condition is everything since the last bracket, comma or branch
+                while let Some(MathValue::Op(op)) = operators.peak() {
+                    if !table.has_operator(op) {
+                        break;
+                    }
+                    if let Some(top_of_stack) = operators.pop() {
+                        output.push(top_of_stack);
+                    }
+                }
+                start_jump(MathValue::JumpUnless(0), token.span, output, jumps);
+                operators.push(token);
+            } else if symbol == ":" {
+                // Finish the first branch, back to the '?' it belongs to
+                loop {
+                    match operators.pop() {
+                        Some(top) if top.value.is_op("?") => break,
+                        Some(top) if matches!(&top.value, MathValue::Op(op) if op != "(") => output_operator(top, output, jumps)?,
+                        _ => return Err(RpnError::DanglingOperator { op: symbol.to_string(), span: token.span }),
+                    }
+                }
+                start_jump(MathValue::Jump(0), token.span, output, jumps);
+                operators.push(token);
             }
             else {
-                let err_msg = format!("Invalid operator: '{}'", token);
-                return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, err_msg)));
+                return Err(RpnError::InvalidOperator { op: symbol.to_string(), span: token.span });
             }
         }            
     }
     Ok(())
 }
 
+/// Moves an operator to the output. A ':' ends the second branch of its conditional,
+/// and a '?' can only leave the operators once its ':' is found
+fn output_operator(token: Token, output: &mut Stack, jumps: &mut Vec<usize>) -> Result<(), RpnError> {
+    match &token.value {
+        MathValue::Op(op) if op == ":" => land_jump(output, jumps),
+        MathValue::Op(op) if op == "?" => return Err(RpnError::DanglingOperator { op: op.to_string(), span: token.span }),
+        _ => output.push(token),
+    }
+    Ok(())
+}
+
+/// Outputs a jump to be given its length once the branch it skips is finished
+fn start_jump(jump: MathValue, span: Span, output: &mut Stack, jumps: &mut Vec<usize>) {
+    let ends_branch = matches!(jump, MathValue::Jump(_));
+    output.push(Token::new(jump, span));
+    // The jump past the second branch is the last token of the first
+    if ends_branch {
+        land_jump(output, jumps);
+    }
+    jumps.push(output.len() - 1);
+}
+
+/// Gives the latest unfinished jump the length of everything output after it
+fn land_jump(output: &mut Stack, jumps: &mut Vec<usize>) {
+    if let Some(position) = jumps.pop() {
+        set_jump_length(output, position);
+    }
+}
+
+fn set_jump_length(output: &mut Stack, position: usize) {
+    let length = output.len() - position - 1;
+    if let Some(Token { value: MathValue::JumpUnless(skip) | MathValue::Jump(skip), .. }) = output.elements.get_mut(position) {
+        *skip = length;
+    }
+}
+
+/// Moves operators to the output until an opening bracket is on top,
+/// leaving the bracket on the operator stack. Fails with `missing` if there is no bracket
+fn pop_to_bracket(operators: &mut Stack, output: &mut Stack, jumps: &mut Vec<usize>, missing: RpnError) -> Result<(), RpnError> {
+    loop {
+        match operators.peak() {
+            Some(value) if value.is_op("(") => return Ok(()),
+            Some(_) => {
+                if let Some(top_of_stack) = operators.pop() {
+                    output_operator(top_of_stack, output, jumps)?;
+                }
+            },
+            None => return Err(missing),
+        }
+    }
+}
+
 #[cfg(test)]
 mod shunting_yard_tests {
     use super::*;
-    use shunting_yard::convert_in_to_post_fix;
+    use shunting_yard::{convert_in_to_post_fix, convert_in_to_post_fix_with};
     use rpn_convert_unit_tests::*;
 
     #[test]
@@ -108,5 +208,53 @@ mod shunting_yard_tests {
     #[test]
     fn test_alge_complex() {
         alge_complex(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_unary() {
+        unary(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_functions() {
+        functions(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_constants() {
+        constants(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_modulo() {
+        modulo(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_implicit_multiplication() {
+        implicit_multiplication(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_identifiers() {
+        identifiers(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_number_literals() {
+        number_literals(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_custom_operators() {
+        custom_operators(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_multi_char_operators() {
+        multi_char_operators(convert_in_to_post_fix_with);
+    }
+    #[test]
+    fn test_comparisons() {
+        comparisons(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_conditionals() {
+        conditionals(convert_in_to_post_fix);
+    }
+    #[test]
+    fn test_associativity() {
+        associativity(convert_in_to_post_fix);
     }        
 }
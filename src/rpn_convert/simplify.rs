@@ -0,0 +1,404 @@
+use super::*;
+
+/// A rewrite of a single node, whose operands are already simplified.
+/// Returns None if the rule does not apply.
+pub type Rule = fn(&Expr) -> Option<Expr>;
+
+/// Rewrites trees to a simpler, canonical form by applying its rules from the
+/// leaves up until none apply. In the canonical form a product keeps its number
+/// in front (`2 * x`) and a sum keeps its number at the end (`x + 2`).
+#[derive(Debug, Clone)]
+pub struct Simplifier {
+    rules: Vec<Rule>,
+}
+
+impl Default for Simplifier {
+    fn default() -> Self {
+        Simplifier::new()
+    }
+}
+
+/// Passes over the tree before giving up on reaching a fixed point
+const MAX_PASSES: usize = 64;
+
+impl Simplifier {
+    /// Simplifier with the built-in rules: constant folding, identities,
+    /// like terms, power merging and reordering into canonical form
+    pub fn new() -> Simplifier {
+        Simplifier {
+            rules: vec![fold_constants, identities, like_terms, merge_powers, reorder],
+        }
+    }
+
+    /// Simplifier without any rules
+    pub fn empty() -> Simplifier {
+        Simplifier { rules: Vec::new() }
+    }
+
+    /// Adds a rule, tried after the rules already added
+    pub fn add_rule(&mut self, rule: Rule) -> &mut Simplifier {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn simplify(&self, expr: &Expr) -> Expr {
+        let mut expr = expr.clone();
+        for _ in 0..MAX_PASSES {
+            let next = self.pass(&expr);
+            if next == expr {
+                break;
+            }
+            expr = next;
+        }
+        expr
+    }
+
+    /// Simplifies the operands, then applies the first rule that matches
+    fn pass(&self, expr: &Expr) -> Expr {
+        expr.fold(&mut Pass(self))
+    }
+
+    fn apply(&self, expr: Expr) -> Expr {
+        self.rules.iter().find_map(|rule| rule(&expr)).unwrap_or(expr)
+    }
+}
+
+/// One pass over the tree, rewriting each part once its operands are rewritten
+struct Pass<'a>(&'a Simplifier);
+impl Fold for Pass<'_> {
+    type Output = Expr;
+
+    fn number(&mut self, value: f64) -> Expr {
+        self.0.apply(Expr::Number(value))
+    }
+    fn boolean(&mut self, value: bool) -> Expr {
+        self.0.apply(Expr::Bool(value))
+    }
+    fn variable(&mut self, name: &str) -> Expr {
+        self.0.apply(Expr::variable(name))
+    }
+    fn binary(&mut self, op: &str, left: Expr, right: Expr) -> Expr {
+        self.0.apply(Expr::binary(op, left, right))
+    }
+    fn unary(&mut self, op: &str, operand: Expr) -> Expr {
+        self.0.apply(Expr::unary(op, operand))
+    }
+    fn call(&mut self, name: &str, args: Vec<Expr>) -> Expr {
+        self.0.apply(Expr::call(name, args))
+    }
+}
+
+/// Simplifies with the built-in rules
+pub fn simplify(expr: &Expr) -> Expr {
+    Simplifier::new().simplify(expr)
+}
+
+fn number(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn is_number(expr: &Expr, value: f64) -> bool {
+    number(expr) == Some(value)
+}
+
+/// The operator and operands of a binary expression using one of the operators
+fn binary<'a>(expr: &'a Expr, ops: &[&str]) -> Option<(&'a str, &'a Expr, &'a Expr)> {
+    match expr {
+        Expr::Binary { op, left, right } if ops.contains(&op.as_str()) => Some((op, left, right)),
+        _ => None,
+    }
+}
+
+/// The operand of a negation
+fn negated(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Unary { op, operand } if op == NEGATE => Some(operand),
+        _ => None,
+    }
+}
+
+/// Calculates operators and functions whose operands are all numbers.
+/// Results that are not finite, and % or // of negatives whose answer
+/// depends on the modulo mode, are left as they are.
+/// On its own in a `Simplifier`, it calculates the parts made only of numbers.
+pub fn fold_constants(expr: &Expr) -> Option<Expr> {
+    let answer = match expr {
+        Expr::Binary { op, left, right } => evaluate::calculate_constant(op, &[number(left)?, number(right)?])?,
+        Expr::Unary { op, operand } => evaluate::calculate_constant(op, &[number(operand)?])?,
+        Expr::Call { name, args } => {
+            let function = functions::builtin(name).filter(|function| function.arity == args.len())?;
+            let args = args.iter().map(number).collect::<Option<Vec<f64>>>()?;
+            Some(function.call(&args)).filter(|answer| answer.is_finite())?
+        },
+        _ => return None,
+    };
+    Some(Expr::Number(answer))
+}
+
+/// Removes operations that do nothing, and those that always give 0 or 1
+fn identities(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Unary { .. } => negated(negated(expr)?).cloned(),
+        Expr::Binary { op, left, right } => {
+            let (left, right) = (left.as_ref(), right.as_ref());
+            match op.as_str() {
+                "+" if is_number(left, 0.0) => Some(right.clone()),
+                "+" | "-" if is_number(right, 0.0) => Some(left.clone()),
+                "-" if is_number(left, 0.0) => Some(Expr::unary(NEGATE, right.clone())),
+                "-" if left == right => Some(Expr::Number(0.0)),
+                "*" if is_number(left, 0.0) || is_number(right, 0.0) => Some(Expr::Number(0.0)),
+                "*" if is_number(left, 1.0) => Some(right.clone()),
+                "*" | "/" if is_number(right, 1.0) => Some(left.clone()),
+                "*" if is_number(left, -1.0) => Some(Expr::unary(NEGATE, right.clone())),
+                "/" if is_number(left, 0.0) => Some(Expr::Number(0.0)),
+                "/" if left == right => Some(Expr::Number(1.0)),
+                "^" if is_number(right, 1.0) => Some(left.clone()),
+                "^" if is_number(right, 0.0) || is_number(left, 1.0) => Some(Expr::Number(1.0)),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Splits a term into its number and the rest, `-3 * x` into (-3, x)
+fn split_term(expr: &Expr) -> (f64, Option<&Expr>) {
+    let mut sign = 1.0;
+    let mut expr = expr;
+    while let Some(operand) = negated(expr) {
+        sign = -sign;
+        expr = operand;
+    }
+    if let Some(n) = number(expr) {
+        return (sign * n, None);
+    }
+    match binary(expr, &["*"]).and_then(|(_, left, right)| Some((number(left)?, right))) {
+        Some((coefficient, rest)) => (sign * coefficient, Some(rest)),
+        None => (sign, Some(expr)),
+    }
+}
+
+fn make_term(coefficient: f64, rest: Expr) -> Expr {
+    match coefficient {
+        0.0 => Expr::Number(0.0),
+        1.0 => rest,
+        -1.0 => Expr::unary(NEGATE, rest),
+        _ => Expr::binary("*", Expr::Number(coefficient), rest),
+    }
+}
+
+/// Collects terms that differ only by their number, `2 * x + x` into `3 * x`,
+/// including the last term of a longer sum
+fn like_terms(expr: &Expr) -> Option<Expr> {
+    let (outer, left, right) = binary(expr, &["+", "-"])?;
+    let combine = |a: &Expr, op: &str, b: &Expr| -> Option<Expr> {
+        let ((a, rest), (b, other)) = (split_term(a), split_term(b));
+        let rest = rest.filter(|rest| Some(*rest) == other)?;
+        let coefficient = if op == "+" { a + b } else { a - b };
+        Some(make_term(coefficient, rest.clone()))
+    };
+    if let Some(term) = combine(left, outer, right) {
+        return Some(term);
+    }
+    // (p + a) - b is p + (a - b), and (p - a) - b is p - (a + b)
+    let (inner, rest, last) = binary(left, &["+", "-"])?;
+    let op = if inner == outer { "+" } else { "-" };
+    let combined = combine(last, op, right)?;
+    Some(Expr::binary(inner, rest.clone(), combined))
+}
+
+/// Splits a power into its base and exponent, taking `x` as `x ^ 1`
+fn split_power(expr: &Expr) -> (Expr, Expr) {
+    match binary(expr, &["^"]) {
+        Some((_, base, exponent)) => (base.clone(), exponent.clone()),
+        None => (expr.clone(), Expr::Number(1.0)),
+    }
+}
+
+/// Flattens a chain of `*` and `/` into its factors in order, false for those dividing
+fn factors(expr: &Expr) -> Vec<(&Expr, bool)> {
+    let mut found = Vec::new();
+    let mut pending = vec![(expr, true)];
+    while let Some((expr, multiplies)) = pending.pop() {
+        match binary(expr, &["*", "/"]) {
+            Some((op, left, right)) => pending.extend([(right, (op == "*") == multiplies), (left, multiplies)]),
+            None => found.push((expr, multiplies)),
+        }
+    }
+    found
+}
+
+/// Merges powers of the same base anywhere in a product, `x * y * x ^ 2 / y` into
+/// `x ^ (1 + 2) * y ^ (1 - 1)`, and `(x ^ 2) ^ 3` into `x ^ (2 * 3)`
+fn merge_powers(expr: &Expr) -> Option<Expr> {
+    if let Some((_, left, right)) = binary(expr, &["^"]) {
+        // Only whole outer exponents, as (x ^ 2) ^ 0.5 is |x|
+        let (_, base, inner) = binary(left, &["^"]).filter(|_| number(right).is_some_and(|n| n.fract() == 0.0))?;
+        return Some(Expr::binary("^", base.clone(), Expr::binary("*", inner.clone(), right.clone())));
+    }
+    binary(expr, &["*", "/"])?;
+    // Each base with its exponents added up, in order of first use
+    let mut powers: Vec<(Expr, Expr)> = Vec::new();
+    let mut merged = false;
+    for (factor, multiplies) in factors(expr) {
+        let (base, exponent) = split_power(factor);
+        let position = powers.iter().position(|(seen, _)| *seen == base && number(&base).is_none());
+        match (position, multiplies) {
+            (Some(index), _) => {
+                let op = if multiplies { "+" } else { "-" };
+                powers[index].1 = Expr::binary(op, powers[index].1.clone(), exponent);
+                merged = true;
+            },
+            (None, true) => powers.push((base, exponent)),
+            (None, false) => powers.push((base, Expr::unary(NEGATE, exponent))),
+        }
+    }
+    if !merged {
+        return None;
+    }
+    powers.into_iter()
+        .map(|(base, exponent)| Expr::binary("^", base, exponent))
+        .reduce(|product, factor| Expr::binary("*", product, factor))
+}
+
+/// Moves numbers to the front of products and the end of sums, so they meet and fold
+fn reorder(expr: &Expr) -> Option<Expr> {
+    let (op, left, right) = binary(expr, &["*", "+", "-"])?;
+    // A product with its number in front, as the number and the rest
+    let scaled = |expr| binary(expr, &["*"]).filter(|(_, inner, _)| number(inner).is_some()).map(|(_, inner, rest)| (inner, rest));
+    let (left_number, right_number) = (number(left).is_some(), number(right).is_some());
+    match (op, scaled(left), scaled(right)) {
+        // 2 * (3 * x) and (2 * x) * 3 are (2 * 3) * x
+        ("*", _, Some((inner, rest))) if left_number => {
+            Some(Expr::binary("*", Expr::binary("*", left.clone(), inner.clone()), rest.clone()))
+        },
+        ("*", Some((inner, rest)), _) if right_number => {
+            Some(Expr::binary("*", Expr::binary("*", right.clone(), inner.clone()), rest.clone()))
+        },
+        ("*", _, _) if right_number && !left_number => Some(Expr::binary("*", right.clone(), left.clone())),
+        // x * (2 * y) is 2 * (x * y)
+        ("*", _, Some((inner, rest))) if !left_number => {
+            Some(Expr::binary("*", inner.clone(), Expr::binary("*", left.clone(), rest.clone())))
+        },
+        ("*", _, _) => None,
+        ("+", _, _) if left_number && !right_number => Some(Expr::binary("+", right.clone(), left.clone())),
+        _ => {
+            let (inner, rest, last) = binary(left, &["+", "-"]).filter(|(_, _, last)| number(last).is_some())?;
+            match number(right) {
+                // (p + 1) + 2 is p + (1 + 2)
+                Some(_) => {
+                    let op = if inner == op { "+" } else { "-" };
+                    Some(Expr::binary(inner, rest.clone(), Expr::binary(op, last.clone(), right.clone())))
+                },
+                // (p + 1) + y is (p + y) + 1
+                None => Some(Expr::binary(inner, Expr::binary(op, rest.clone(), right.clone()), last.clone())),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod simplify_tests {
+    use super::*;
+
+    fn simplified(input: &str) -> String {
+        simplify(&ast_tree::parse(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn constant_folding() {
+        assert_eq!("10", simplified("2 * (3 + 2)"));
+        assert_eq!("x + 6", simplified("x + 2 * 3"));
+        assert_eq!("6 * x", simplified("2 * 3 * x"));
+        assert_eq!("6 * x", simplified("2 * x * 3"));
+        assert_eq!("x + 5", simplified("2 + x + 3"));
+        assert_eq!("1 / 0 + x", simplified("1 / 0 + x"));
+        assert_eq!("¬7 % 2", simplified("-7 % 2"));
+    }
+    #[test]
+    fn only_folding() {
+        let mut folder = Simplifier::empty();
+        folder.add_rule(fold_constants);
+        let folded = |input: &str| folder.simplify(&ast_tree::parse(input).unwrap()).to_stack().as_string();
+        assert_eq!("x 8 * 1 +", folded("x * 2^3 + sqrt(4) / 2"));
+        assert_eq!("x 2 1 0 / * +", folded("x + 2 * (1/0)"));
+        assert_eq!("y 4 max", folded("max(y, 2 * 2)"));
+        assert_eq!("x 1 * 0 +", folded("x * 1 + 0"));
+        assert_eq!("x 0 > ?2 6 :3 x 2 +", folded("x > 0 ? 2 * 3 : x + 1 * 2"));
+    }
+    #[test]
+    fn identities() {
+        assert_eq!("x", simplified("x * 1 + 0"));
+        assert_eq!("y", simplified("0 + y"));
+        assert_eq!("x", simplified("x ^ 1"));
+        assert_eq!("0", simplified("x - x"));
+        assert_eq!("1", simplified("(a + b) / (a + b)"));
+        assert_eq!("0", simplified("sin(x) * 0"));
+        assert_eq!("x", simplified("--x"));
+    }
+    #[test]
+    fn like_terms() {
+        assert_eq!("3 * x", simplified("2 * x + x"));
+        assert_eq!("y + 2 * x", simplified("y + x + x"));
+        assert_eq!("0", simplified("2 * x - x * 2"));
+        assert_eq!("-x", simplified("x - 2 * x"));
+        assert_eq!("a - 2 * sin(x)", simplified("a - sin(x) - sin(x)"));
+    }
+    #[test]
+    fn powers() {
+        assert_eq!("x ^ 2", simplified("x * x"));
+        assert_eq!("x ^ 5", simplified("x ^ 2 * x ^ 3"));
+        assert_eq!("x ^ 6", simplified("(x ^ 2) ^ 3"));
+        assert_eq!("(x ^ 2) ^ 0.5", simplified("(x ^ 2) ^ 0.5"));
+        assert_eq!("2 * x ^ 2 * y", simplified("2 * x * y * x / y * y"));
+        assert_eq!("1", simplified("x ^ 2 / x ^ 2"));
+    }
+    #[test]
+    fn derivatives() {
+        let derivative = derivative::differentiate(&ast_tree::parse("x / (x + 1)").unwrap(), "x").unwrap();
+        assert_eq!("1 / (x + 1) ^ 2", simplify(&derivative).to_string());
+    }
+    #[test]
+    fn same_value() {
+        let mut context = Context::new();
+        context.set_variable("x", 1.7).set_variable("y", -0.4);
+        let inputs = [
+            "x * 1 + 0 * y", "2 * x * y * x / y * y", "(x + 2) * 3 - x - x - x", "x ^ 2 * x ^ -1 + y / y",
+            "sin(2 * pi) * x + cos(0) * y", "(1 + 2 + x + 3) * (y - y + 1)",
+        ];
+        for input in inputs {
+            let expr = ast_tree::parse(input).unwrap();
+            let before = evaluate::evaluate(&expr.to_stack(), &context).unwrap();
+            let after = evaluate::evaluate(&simplify(&expr).to_stack(), &context).unwrap();
+            assert!((before - after).abs() < 1e-9, "{}: {} vs {}", input, before, after);
+        }
+    }
+    #[test]
+    fn custom_rules() {
+        fn pythagoras(expr: &Expr) -> Option<Expr> {
+            let square = |expr: &Expr, name: &str| match expr {
+                Expr::Binary { op, left, right } if op == "^" && **right == Expr::Number(2.0) => match left.as_ref() {
+                    Expr::Call { name: called, args } if called == name => args.first().cloned(),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match expr {
+                Expr::Binary { op, left, right } if op == "+" => {
+                    let angle = square(left, "sin")?;
+                    (Some(angle) == square(right, "cos")).then_some(Expr::Number(1.0))
+                },
+                _ => None,
+            }
+        }
+        let expr = ast_tree::parse("(sin(x) ^ 2 + cos(x) ^ 2) * y").unwrap();
+        assert_eq!("(sin(x) ^ 2 + cos(x) ^ 2) * y", simplify(&expr).to_string());
+        let mut simplifier = Simplifier::new();
+        simplifier.add_rule(pythagoras);
+        assert_eq!("y", simplifier.simplify(&expr).to_string());
+        assert_eq!(expr, Simplifier::empty().simplify(&expr));
+    }
+}
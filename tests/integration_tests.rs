@@ -3,13 +3,24 @@
 mod integration {
     // Solve Numerical
     mod test_solve_numerical {
-        use rpn_lib::solve_numerical;
+        use rpn_lib::{solve_numerical, RpnError, Span};
         #[test]    
         fn invalid_operation() {
             let error = solve_numerical("10=2").unwrap_err();
             assert_eq!("Invalid operator: '='".to_string(), error.to_string());
         }
 
+        #[test]
+        fn invalid_operation_span() {
+            match solve_numerical("10 = 2").unwrap_err() {
+                RpnError::InvalidOperator { op, span } => {
+                    assert_eq!("=", op);
+                    assert_eq!(Span::new(3, 4), span);
+                },
+                error => panic!("Expected an invalid operator, got {:?}", error),
+            }
+        }
+
         #[test]
         fn division_by_zero() {
             assert_eq!(f64::INFINITY, solve_numerical("10/0").unwrap());
@@ -20,6 +31,23 @@ mod integration {
             assert_eq!(10.0, solve_numerical("(!5+!5) * !1").unwrap());
         }
 
+        #[test]
+        fn unary_minus() {
+            assert_eq!(-6.0, solve_numerical("3*-2").unwrap());
+            assert_eq!(-4.0, solve_numerical("-2^2").unwrap());
+            assert_eq!(-9.0, solve_numerical("-(1+2)*3").unwrap());
+            assert_eq!(7.0, solve_numerical("5 - -2").unwrap());
+            assert_eq!(5.0, solve_numerical("+5 * +1").unwrap());
+        }
+
+        #[test]
+        fn functions() {
+            assert_eq!(7.0, solve_numerical("sqrt(16) + max(2, 3)").unwrap());
+            assert_eq!(1.0, solve_numerical("sin(0) + cos(0) * round(0.6)^2").unwrap());
+            assert_eq!(3.0, solve_numerical("log(2, 8) * abs(-ln(exp(1)))").unwrap());
+            assert_eq!(-2.0, solve_numerical("min(floor(-1.5), ceil(-2.5))").unwrap());
+        }
+
         #[test]
         fn simple_addition() {
             assert_eq!(10.0, solve_numerical("3 + 7").unwrap());
@@ -45,14 +73,265 @@ mod integration {
             assert_eq!(12.0, solve_numerical("4 + 2 * 5 - 8 / 4").unwrap());
         }
 
+        #[test]
+        fn right_associative_power() {
+            assert_eq!(512.0, solve_numerical("2^3^2").unwrap());
+            assert_eq!(64.0, solve_numerical("(2^3)^2").unwrap());
+        }
+
+        #[test]
+        fn modulo_and_integer_division() {
+            assert_eq!(7.0, solve_numerical("17 % 5 * 2 + 7 // 2").unwrap());
+            assert_eq!(-1.0, solve_numerical("-7 % 3").unwrap());
+            assert_eq!(-2.0, solve_numerical("-7 // 3").unwrap());
+            assert_eq!(1.5, solve_numerical("5.5 % 2").unwrap());
+        }
+
+        #[test]
+        fn number_literals() {
+            assert_eq!(0.003, solve_numerical("1.5e-3 * 2").unwrap());
+            assert_eq!(41.0, solve_numerical("0x1F + 0b1010").unwrap());
+            assert_eq!(1_000_015.5, solve_numerical("1_000_000 + 0o17 + .5").unwrap());
+            match solve_numerical("1 + 1.2.3").unwrap_err() {
+                RpnError::NumberParse { text, span } => {
+                    assert_eq!("1.2.3", text);
+                    assert_eq!(Span::new(4, 9), span);
+                },
+                error => panic!("Expected an invalid number, got {:?}", error),
+            }
+            // A space ends a literal, so the e has no exponent and is a name
+            assert!(matches!(solve_numerical("1e + 1"), Err(RpnError::UnboundVariables { .. })));
+            assert!(matches!(solve_numerical("1 2 + 3"), Err(RpnError::LeftoverOperands { .. })));
+            assert!(matches!(solve_numerical("0x + 1"), Err(RpnError::NumberParse { .. })));
+        }
+
+        #[test]
+        fn power_operators() {
+            use rpn_lib::get_rpn_yard;
+            assert_eq!(1024.0, solve_numerical("2**10").unwrap());
+            assert_eq!("2 3 2 ^ ^", get_rpn_yard("2 ** 3 ^ 2").unwrap());
+            assert!(solve_numerical("2 * * 3").is_err());
+        }
+
+        #[test]
+        fn floored_modulo() {
+            use rpn_lib::{solve_with_context, Context, ModuloMode};
+            let mut context = Context::new();
+            context.set_modulo_mode(ModuloMode::Floored);
+            assert_eq!(2.0, solve_with_context("-7 % 3", &context).unwrap());
+            assert_eq!(-2.0, solve_with_context("7 % -3", &context).unwrap());
+            assert_eq!(-3.0, solve_with_context("-7 // 3", &context).unwrap());
+        }
+
+        #[test]
+        fn rpn_input() {
+            use rpn_lib::solve_rpn;
+            assert_eq!(14.0, solve_rpn("3 4 + 2 *").unwrap());
+            assert_eq!(-1.0, solve_rpn("2 -3 + 1 ~ ^").unwrap());
+            match solve_rpn("3 4 * -").unwrap_err() {
+                RpnError::StackUnderflow { op, span } => {
+                    assert_eq!("-", op);
+                    assert_eq!(Span::new(6, 7), span);
+                },
+                error => panic!("Expected a stack underflow, got {:?}", error),
+            }
+        }
+
         #[test]
         fn complex_expression() {
             assert_eq!(-0.41000000000000014, solve_numerical("3.34 + 12 * ( 4 - 2 ) / !6.4").unwrap());
         }
     }
 
+    // Solve With Variables
+    mod test_solve_with_variables {
+        use rpn_lib::{solve_with_variables, solve_numerical, get_rpn_yard};
+        use std::collections::HashMap;
+
+        #[test]
+        fn formula_with_parameters() {
+            let variables = HashMap::from([
+                ("x".to_string(), 3.5),
+                ("rate".to_string(), 2.0),
+            ]);
+            assert_eq!(8.5, solve_with_variables("x * rate + !x + 3*rate - 1", &variables).unwrap());
+        }
+
+        #[test]
+        fn constants_and_context() {
+            use rpn_lib::{solve_with_context, Context};
+            assert_eq!(2.0 * std::f64::consts::PI * 3.0, solve_numerical("2*pi*3").unwrap());
+            let mut context = Context::new();
+            context.set_constant("g", 9.81).set_variable("t", 2.0);
+            assert_eq!(19.62, solve_with_context("g * t", &context).unwrap());
+            assert_eq!("2 pi * r *", get_rpn_yard("2 * pi * r").unwrap());
+        }
+
+        #[test]
+        fn stored_rpn() {
+            use rpn_lib::solve_rpn_with_context;
+            let variables = HashMap::from([
+                ("x".to_string(), 3.5),
+                ("rate".to_string(), 2.0),
+            ]);
+            let context = rpn_lib::Context::with_variables(&variables);
+            for input in ["x * rate + !x + 3*rate - 1", "-(x - 1) ^ 2 // rate", "max(x, 2 * rate) % 3"] {
+                let rpn = get_rpn_yard(input).unwrap();
+                assert_eq!(
+                    solve_with_variables(input, &variables).unwrap(),
+                    solve_rpn_with_context(&rpn, &context).unwrap(),
+                    "{}", rpn
+                );
+            }
+        }
+
+        #[test]
+        fn identifiers() {
+            let variables = HashMap::from([
+                ("flow_rate2".to_string(), 1.5),
+                ("Vmax".to_string(), 4.0),
+                ("θ".to_string(), 0.0),
+            ]);
+            assert_eq!(7.0, solve_with_variables("flow_rate2 * Vmax + cos(θ)", &variables).unwrap());
+            assert_eq!("flow_rate2 Vmax * θ cos +", get_rpn_yard("flow_rate2 * Vmax + cos(θ)").unwrap());
+        }
+
+        #[test]
+        fn implicit_multiplication() {
+            use rpn_lib::{solve_with_options, get_rpn_with, Context, ParseOptions};
+            let mut options = ParseOptions::new();
+            options.set_implicit_multiplication(true);
+            let mut context = Context::new();
+            context.set_variable("a", 3.0).set_variable("b", 1.0);
+            assert_eq!(8.0, solve_with_options("(a+b)(a-b)", &options, &context).unwrap());
+            assert_eq!(13.0, solve_with_options("2a^2 - 5b", &options, &context).unwrap());
+            assert_eq!("2 a * 3 b 1 + * +", get_rpn_with("2a + 3(b+1)", &options).unwrap());
+            assert_eq!("2a 3 b 1 + * +", get_rpn_with("2a + 3*(b+1)", &ParseOptions::new()).unwrap());
+        }
+
+        #[test]
+        fn defined_functions() {
+            use rpn_lib::{solve_with_context, Context, RpnError};
+            let mut context = Context::new();
+            context.define("area(w, h) = w * h").unwrap().define("volume(w, h, d) = area(w, h) * d").unwrap();
+            context.set_variable("d", 100.0);
+            assert_eq!(30.0, solve_with_context("volume(2, 3, 5)", &context).unwrap());
+            assert_eq!(106.0, solve_with_context("area(2, 3) + d", &context).unwrap());
+            match solve_with_context("volume(2, 3)", &context).unwrap_err() {
+                RpnError::ArityMismatch { name, expected, found, .. } => {
+                    assert_eq!(("volume", 3, 2), (name.as_str(), expected, found));
+                },
+                error => panic!("Expected an arity mismatch, got {:?}", error),
+            }
+            context.define("forever(x) = forever(x) + 1").unwrap();
+            assert!(matches!(solve_with_context("forever(1)", &context), Err(RpnError::RecursionLimit { .. })));
+        }
+
+        #[test]
+        fn defined_functions_in_rpn() {
+            use rpn_lib::{solve_rpn_with_context, solve_prefix_with_context, rpn_to_in_with_context, get_prefix, Context};
+            let mut context = Context::new();
+            context.define("f(x, y) = x^2 + y").unwrap();
+            let rpn = get_rpn_yard("f(3, 4) * 2").unwrap();
+            assert_eq!("3 4 f 2 *", rpn);
+            assert_eq!(26.0, solve_rpn_with_context(&rpn, &context).unwrap());
+            assert_eq!("f(3, 4) * 2", rpn_to_in_with_context(&rpn, &context).unwrap());
+            assert_eq!(26.0, solve_prefix_with_context(&get_prefix("f(3, 4) * 2").unwrap(), &context).unwrap());
+        }
+
+        /// Defines a function in the REPL then solves rpn calling it with "p"
+        #[test]
+        fn defined_functions_in_repl() {
+            use std::io::Write;
+            use std::process::{Command, Stdio};
+            let mut repl = Command::new(env!("CARGO_BIN_EXE_rpn"))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            repl.stdin.take().unwrap().write_all(b"d\nf(x, y) = x^2 + y\np\n3 4 f\nq\n").unwrap();
+            let output = String::from_utf8(repl.wait_with_output().unwrap().stdout).unwrap();
+            assert!(output.contains("3 4 f = 13"), "{}", output);
+        }
+
+        #[test]
+        fn custom_operators() {
+            use rpn_lib::{solve_with_options, solve_rpn_with_context, get_rpn_with, Context, ParseOptions, OperatorTable, Operator, Associativity};
+            // Parallel resistance, binding like * and /
+            let mut operators = OperatorTable::new();
+            operators.add("|", Operator::new(2, Associativity::Left), |args| 1.0 / (1.0 / args[0] + 1.0 / args[1])).unwrap();
+            let mut options = ParseOptions::new();
+            options.set_operators(operators.clone());
+            let mut context = Context::new();
+            context.set_operators(operators).set_variable("r", 6.0);
+            assert_eq!(4.0, solve_with_options("2 + 3 | r", &options, &context).unwrap());
+            assert_eq!("2 3 r | +", get_rpn_with("2 + 3 | r", &options).unwrap());
+            assert_eq!(2.0, solve_rpn_with_context("3 r |", &context).unwrap());
+            assert!(solve_with_options("2 + 3 | r", &ParseOptions::new(), &context).is_err());
+            // Symbols longer than one char
+            let mut operators = OperatorTable::new();
+            operators.add("<<", Operator::new(4, Associativity::Left), |args| args[0] * 2f64.powf(args[1])).unwrap();
+            options.set_operators(operators.clone());
+            context.set_operators(operators);
+            assert_eq!(16.0, solve_with_options("1 << 3 + 1", &options, &context).unwrap());
+            assert_eq!("1 3 1 + <<", get_rpn_with("1 << 3 + 1", &options).unwrap());
+            assert_eq!(12.0, solve_rpn_with_context("3 2 <<", &context).unwrap());
+        }
+
+        #[test]
+        fn conditions() {
+            use rpn_lib::{solve_condition, solve_value, solve_with_context, Context, RpnError, Value, ValueType};
+            let rule = "pressure < 100 and temp >= 0";
+            assert_eq!("pressure 100 < temp 0 >= and", get_rpn_yard(rule).unwrap());
+            assert_eq!(get_rpn_yard(rule), rpn_lib::get_rpn_tree(rule));
+            let mut context = Context::new();
+            context.set_variable("pressure", 95.5).set_variable("temp", 12.0);
+            assert_eq!(Ok(true), solve_condition(rule, &context));
+            assert_eq!(Ok(rule.to_string()), rpn_lib::rpn_to_in(&get_rpn_yard(rule).unwrap()));
+            context.set_variable("temp", -3.0);
+            assert_eq!(Ok(false), solve_condition(rule, &context));
+            assert_eq!(Ok(true), solve_condition("not (temp >= 0) or pressure != 95.5", &context));
+            assert_eq!(Ok(Value::Number(92.5)), solve_value("pressure + temp", &context));
+            match solve_with_context(rule, &context).unwrap_err() {
+                RpnError::TypeMismatch { expected, span } => assert_eq!((ValueType::Number, 0, 28), (expected, span.start, span.end)),
+                error => panic!("Expected a type mismatch, got {:?}", error),
+            }
+            assert!(matches!(solve_condition("pressure + temp", &context), Err(RpnError::TypeMismatch { expected: ValueType::Bool, .. })));
+        }
+
+        #[test]
+        fn conditionals() {
+            use rpn_lib::{solve_with_context, solve_rpn_with_context, solve_prefix_with_context, get_prefix, Context};
+            // A tiered tariff, with the second tier only evaluated above 100 units
+            let tariff = "units <= 100 ? units * 0.2 : 20 + (units - 100) * 0.35";
+            let rpn = get_rpn_yard(tariff).unwrap();
+            assert_eq!("units 100 <= ?4 units 0.2 * :7 20 units 100 - 0.35 * +", rpn);
+            assert_eq!(rpn, rpn_lib::get_rpn_tree("if(units <= 100, units * 0.2, 20 + (units - 100) * 0.35)").unwrap());
+            let mut context = Context::new();
+            for (units, cost) in [(50.0, 10.0), (200.0, 55.0)] {
+                context.set_variable("units", units);
+                assert_eq!(cost, solve_with_context(tariff, &context).unwrap());
+                assert_eq!(cost, solve_rpn_with_context(&rpn, &context).unwrap());
+            }
+            // Clamped values, with nothing divided by zero when the divisor is 0
+            context.set_variable("d", 0.0);
+            assert_eq!(0.0, solve_with_context("d == 0 ? 0 : max(1 / d, 10)", &context).unwrap());
+            let prefix = get_prefix("d == 0 ? 0 : 1 / d").unwrap();
+            assert_eq!("if == d 0 0 / 1 d", prefix);
+            assert_eq!(0.0, solve_prefix_with_context(&prefix, &context).unwrap());
+            assert_eq!("d == 0 ? 0 : 1 / d", rpn_lib::rpn_to_in(&get_rpn_yard("if(d == 0, 0, 1 / d)").unwrap()).unwrap());
+        }
+
+        #[test]
+        fn unbound_variables() {
+            let variables = HashMap::from([("x".to_string(), 1.0)]);
+            let error = solve_with_variables("x + y * z", &variables).unwrap_err();
+            assert_eq!("Unbound variables: y, z".to_string(), error.to_string());
+        }
+    }
+
 
-    mod test_get_rpn {        
+    mod test_get_rpn {      
         use rpn_lib::{
             get_rpn_yard,
             get_rpn_tree
@@ -66,7 +345,7 @@ mod integration {
         
         */
 
-        type RpnReturn = Result<String, Box<dyn std::error::Error>>;
+        type RpnReturn = Result<String, rpn_lib::RpnError>;
 
         pub fn negatives(func: fn(&str) -> RpnReturn) {
             assert_eq!("-5 -5 + -1 *", 
@@ -88,6 +367,64 @@ mod integration {
             func("!4.3a + (b^2-!10)*(x/(1/2))").unwrap());
         }
 
+        pub fn dangling_operators(func: fn(&str) -> RpnReturn) {
+            for input in ["(1+)*2", "(-)+1", "max(x,x^)", "(1 and) or x", "2*(not)"] {
+                assert!(matches!(func(input), Err(rpn_lib::RpnError::DanglingOperator { .. })), "{}", input);
+            }
+        }
+
+        #[test]
+        fn test_dangling_operators() {
+            dangling_operators(get_rpn_yard);
+            dangling_operators(get_rpn_tree);
+        }
+
+        #[test]
+        fn algorithms_agree() {
+            let inputs = [
+                "2^3^2", "2^3^2^1", "(2^3)^2", "8-4-2", "8/4/2*3", "1+2*3^4^5-6/7",
+                "a^b*c^d^e", "!2^3-x^!1", "((a-b)-(c-d))^(e^f)",
+                "max(a, -b)^2", "sin(cos(x)^2)", "atan2(y - 1, -x) * log(2, x)^-2",
+                "a % b * c // d", "a // b // c % d ^ 2",
+            ];
+            for input in inputs {
+                assert_eq!(get_rpn_yard(input).unwrap(), get_rpn_tree(input).unwrap(), "{}", input);
+            }
+        }
+
+        #[test]
+        fn back_to_infix() {
+            use rpn_lib::rpn_to_in;
+            assert_eq!("(31 + 321) * (32 + 54)", rpn_to_in("31 321 + 32 54 + *").unwrap());
+            assert_eq!("c * (a * (b * b + 1) - d123.32 / f9.23)", rpn_to_in("c a b b * 1 + * d123.32 f9.23 / - *").unwrap());
+            for input in ["(31 + 321)*(32+54)", "-(x - 1)^2 // rate", "2^(3^2) - (1 - x)", "max(x, 2 * -rate) % 3", "-x", "y", "2"] {
+                let rpn = get_rpn_yard(input).unwrap();
+                assert_eq!(rpn, get_rpn_tree(&rpn_to_in(&rpn).unwrap()).unwrap(), "{}", input);
+            }
+        }
+
+        #[test]
+        fn prefix() {
+            use rpn_lib::{get_prefix, solve_prefix, solve_numerical};
+            assert_eq!("* + 31 321 + 32 54", get_prefix("(31 + 321)*(32+54)").unwrap());
+            assert_eq!("+ -4.3a * - ^ b 2 -10 / x / 1 2", get_prefix("!4.3a + (b^2-!10)*(x/(1/2))").unwrap());
+            for input in ["(31 + 321)*(32+54)", "2^3^2 - 8/4/2", "-max(1, 2 * -3) // 0.5"] {
+                let prefix = get_prefix(input).unwrap();
+                assert_eq!(solve_numerical(input).unwrap(), solve_prefix(&prefix).unwrap(), "{}", prefix);
+            }
+        }
+
+        #[test]
+        fn folded() {
+            use rpn_lib::get_rpn_folded;
+            assert_eq!("5 x *", get_rpn_folded("(2+3)*x").unwrap());
+            assert_eq!("2 3 + x *", get_rpn_yard("(2+3)*x").unwrap());
+            assert_eq!("x 0.5 * 2 -", get_rpn_folded("x * (2.50/5) - sqrt(!2 + 6)").unwrap());
+            assert_eq!("15.5", get_rpn_folded("(31 + 0)*(1 / 2)").unwrap());
+            assert_eq!("-7 2 % pi -", get_rpn_folded("-7 % 2 - pi").unwrap());
+            assert_eq!("x 0 > ?2 6 :3 x 2 +", get_rpn_folded("x > 0 ? 2 * 3 : x + 1 * 2").unwrap());
+        }
+
         #[test]
         fn test_negatives() {
             negatives(get_rpn_yard);
@@ -108,6 +445,169 @@ mod integration {
             rpn_3(get_rpn_yard);
             rpn_3(get_rpn_tree);
         }
+        #[test]
+        fn long_sums() {
+            use rpn_lib::{get_prefix, get_rpn_folded, get_rpn_simplified, solve_prefix};
+            let sum = vec!["1"; 10000].join(" + ");
+            let rpn = get_rpn_yard(&sum).unwrap();
+            assert_eq!(rpn, get_rpn_tree(&sum).unwrap());
+            assert_eq!(Ok(10000.0), rpn_lib::solve_rpn(&rpn));
+            assert_eq!("+ ".repeat(9999) + &vec!["1"; 10000].join(" "), get_prefix(&sum).unwrap());
+            assert_eq!(Ok(10000.0), solve_prefix(&get_prefix(&sum).unwrap()));
+            assert_eq!("10000", get_rpn_folded(&sum).unwrap());
+            assert_eq!("10000 x *", get_rpn_simplified(&vec!["x"; 10000].join(" - -")).unwrap());
+        }
+
+    }
+
+    mod test_parse {
+        use rpn_lib::{parse, Expr};
+
+        #[test]
+        fn pattern_matching() {
+            match parse("2 * max(x, 1) - y").unwrap() {
+                Expr::Binary { op, left, right } if op == "-" => {
+                    assert_eq!(Expr::Variable("y".to_string()), *right);
+                    let Expr::Binary { op, right: call, .. } = *left else {
+                        panic!("Expected a product, got {:?}", left);
+                    };
+                    assert_eq!("*", op);
+                    assert_eq!(Expr::Call { name: "max".to_string(), args: vec![Expr::Variable("x".to_string()), Expr::Number(1.0)] }, *call);
+                },
+                expr => panic!("Expected a subtraction, got {:?}", expr),
+            }
+            // Operators longer than one char are held by the symbol they are written as
+            assert!(matches!(parse("x < 1 == true"), Ok(Expr::Binary { op, right, .. }) if op == "==" && *right == Expr::Bool(true)));
+        }
+
+        #[test]
+        fn invalid_input() {
+            assert!(parse("2 +* 3").is_err());
+        }
+
+        #[test]
+        fn derivative() {
+            use rpn_lib::differentiate;
+            let cost = parse("(w * x - y)^2 + w^2").unwrap();
+            let gradient = differentiate(&cost, "w").unwrap();
+            assert_eq!("2 * (w * x - y) * x + 2 * w", gradient.to_string());
+            assert_eq!("2 w x * y - * x * 2 w * +", gradient.to_stack().as_string());
+        }
+
+        #[test]
+        fn simplified() {
+            use rpn_lib::{simplify, get_rpn_simplified, solve_simplified, solve_numerical, differentiate, Context, RpnError, Span};
+            assert_eq!("3 x *", get_rpn_simplified("x * 1 + 0 + 2 * x").unwrap());
+            assert_eq!("x 3 ^ y +", get_rpn_simplified("x * x^2 + y - 0*z").unwrap());
+            let derivative = differentiate(&parse("x^3 + 2*x*x").unwrap(), "x").unwrap();
+            assert_eq!("3 * x ^ 2 + 4 * x", simplify(&derivative).to_string());
+            let mut context = Context::new();
+            context.set_variable("x", 2.0);
+            assert_eq!(6.0, solve_simplified("x * 1 + 0 + 2 * x", &context).unwrap());
+            let unbound = solve_simplified("x - x + 1", &Context::new()).unwrap_err();
+            assert_eq!(RpnError::UnboundVariables { variables: vec![("x".to_string(), Span::new(0, 1))] }, unbound);
+            // Simplifying drops the division by 0 that solving directly runs into
+            assert_eq!(0.0, solve_simplified("0 * (1 / 0)", &context).unwrap());
+            assert!(solve_numerical("0 * (1 / 0)").unwrap().is_nan());
+        }
+    }
+
+    // No input should be able to abort the process
+    mod test_no_panics {
+        use rpn_lib::{solve_numerical, solve_with_variables, solve_rpn, rpn_to_in, get_rpn_yard, get_rpn_tree};
+        use rpn_lib::{get_prefix, solve_prefix, parse};
+        use rpn_lib::rpn_convert::{Validate, diagnostic};
+        use std::collections::HashMap;
+        use std::panic;
+
+        const CORPUS: [&str; 53] = [
+            "", " ", "(", ")", "()", "()+1", "3+(*2)", "3+", "+3", "*", "((((", "))))",
+            "1+2)", "(1+2", "3 4", "!!!", "¬", "1..2+3", "x=", "10=2", "(x)(y)",
+            "2^^3", "1/0", "ß+é*ö", "max(", "max(1,", "sin()", "max(1,,2)", "(1,2)",
+            "1,2", "sqrt(1)(2)", "min(1 2)", "//", "1///2", "4%%2",
+            "**", "2***3", "2**", "1.2.3", "0x",
+            "1<=2", "!=", "not", "x and", "1 < 2 == true", "true + 1",
+            "?", "1?2:", "a?b", "if(1,2)", ":1 2", "?9 1", "1 ?1 :0",
+        ];
+        const ALPHABET: [char; 27] = [
+            '1', '2', '0', '.', '+', '-', '*', '/', '^', '%', '(', ')',
+            '!', '¬', 'x', 'y', ' ', '=', '@', 'X', 'é', ',', 'n', 's', '<', '?', ':',
+        ];
 
+        /// Runs every public entry point, returning false if any panicked
+        fn survives(input: &str) -> bool {
+            panic::catch_unwind(|| {
+                let variables = HashMap::from([("x".to_string(), 2.0)]);
+                let _ = solve_numerical(input);
+                let _ = solve_with_variables(input, &variables);
+                let _ = get_rpn_yard(input);
+                let _ = get_rpn_tree(input);
+                let _ = solve_rpn(input);
+                let _ = rpn_to_in(input);
+                let mut options = rpn_lib::ParseOptions::new();
+                options.set_implicit_multiplication(true);
+                let _ = rpn_lib::get_rpn_with(input, &options);
+                let _ = get_prefix(input);
+                let _ = solve_prefix(input);
+                if let Ok(expr) = parse(input) {
+                    let _ = expr.to_string();
+                    let _ = rpn_lib::simplify(&expr).to_string();
+                    let _ = rpn_lib::differentiate(&expr, "x");
+                }
+                for error in Validate::validate_all(input) {
+                    let _ = diagnostic::render(input, &error);
+                }
+                if let Err(error) = solve_with_variables(input, &HashMap::new()) {
+                    let _ = diagnostic::render(input, &error);
+                }
+            }).is_ok()
+        }
+
+        /// Deterministic inputs built from the alphabet using xorshift
+        fn generated(count: usize) -> Vec<String> {
+            let mut state: u64 = 0x2545F4914F6CDD1D;
+            let mut next = move || {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as usize
+            };
+            (0..count).map(|_| {
+                let len = next() % 13;
+                (0..len).map(|_| ALPHABET[next() % ALPHABET.len()]).collect()
+            }).collect()
+        }
+
+        #[test]
+        fn corpus() {
+            let failures: Vec<&str> = CORPUS.iter().copied().filter(|input| !survives(input)).collect();
+            assert!(failures.is_empty(), "Panicked on {:?}", failures);
+        }
+
+        /// Inputs nested deep enough to overflow the stack if followed without a limit
+        fn deep() -> Vec<String> {
+            vec![
+                "(".repeat(3000) + "1" + &")".repeat(3000),
+                "-".repeat(3000) + "1",
+                vec!["x"; 10000].join("^"),
+                vec!["x"; 10000].join("-"),
+                "sin(".repeat(3000) + "x" + &")".repeat(3000),
+                "x ? 1 : ".repeat(3000) + "0",
+                "not ".repeat(3000) + "true",
+            ]
+        }
+
+        #[test]
+        fn deep_inputs() {
+            let failures: Vec<String> = deep().into_iter().filter(|input| !survives(input)).collect();
+            let starts = failures.iter().map(|input| input.chars().take(20).collect()).collect::<Vec<String>>();
+            assert!(failures.is_empty(), "Panicked on inputs starting {:?}", starts);
+        }
+
+        #[test]
+        fn generated_inputs() {
+            let failures: Vec<String> = generated(5000).into_iter().filter(|input| !survives(input)).collect();
+            assert!(failures.is_empty(), "Panicked on {:?}", failures);
+        }
     }
-}
\ No newline at end of file
+}